@@ -1,8 +1,14 @@
+use std::sync::Arc;
+
+use futures::{pin_mut, StreamExt};
 use tokio::runtime::Runtime;
+use chrono::NaiveDate;
 
 use bevy::prelude::*;
-use configparser::ini::Ini;
+use rocks::config;
+use rocks::db_util;
 use rocks::nasa::{self, models::NearEarthObjectResponse};
+use rocks::orbit;
 use bevy_egui::{egui, EguiContext, EguiPlugin};
 
 // in kilometers
@@ -42,56 +48,214 @@ impl Plugin for RocksPlugin {
         .add_plugin(EguiPlugin)
         .init_resource::<UiState>()
         .add_system(controls_ui)
+        .add_system(inspector_ui)
+        .add_system(look_up_selected)
         .add_system(read_new_near_earth_object_data_stream)
+        .init_resource::<DisplaySettings>()
+        .add_system(scale_near_earth_objects)
+        .init_resource::<simulation::SimulationTime>()
+        .add_system(simulation::advance_simulation_time)
+        .add_system(simulation::move_near_earth_objects)
         .add_startup_system(camera::spawn_camera)
-        .add_system(camera::pan_orbit_camera);
+        .add_system(camera::pan_orbit_camera)
+        .init_resource::<picking::Hovered>()
+        .add_startup_system(picking::load_halo)
+        .add_system(picking::hover_near_earth_objects)
+        .add_system(picking::select_near_earth_objects)
+        // Selected is removed with commands during update, removals are only visible in a later stage
+        .add_system_to_stage(CoreStage::PostUpdate, picking::highlight_selected)
+        .add_system(picking::near_earth_object_tooltip);
     }
 }
 
 #[derive(Default, Debug)]
 struct UiState {
     date_range: DateRange,
+    /// shown in the controls window when the last data request failed
+    warning: Option<String>,
+    /// look up every queried object's orbit, costs one api request per object
+    look_up_orbits: bool,
+}
+
+/// How near earth objects are drawn, changed from the controls window
+#[derive(Debug)]
+struct DisplaySettings {
+    /// real sizes are far too small to see at [`UNIT_SIZE`], so diameters are multiplied by this
+    size_exaggeration: f64,
+    /// compress the exaggerated sizes so the largest objects don't hide everything else
+    logarithmic_size: bool,
+}
+
+impl Default for DisplaySettings {
+    fn default() -> Self {
+        DisplaySettings {
+            // a 100m asteroid is about as big as the old fixed size spheres
+            size_exaggeration: 20000.,
+            logarithmic_size: false,
+        }
+    }
+}
+
+impl DisplaySettings {
+    /// Radius in scene units for an object with the diameter in kilometers
+    fn radius(&self, diameter_km: f64) -> f32 {
+        let diameter = diameter_km * self.size_exaggeration / UNIT_SIZE as f64;
+        // ln(1 + x) is close to x for small objects, so only the large ones shrink
+        let diameter = if self.logarithmic_size { diameter.ln_1p() } else { diameter };
+        (diameter / 2.) as f32
+    }
+}
+
+/// Categories near earth objects are colored by
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+enum HazardClass {
+    Normal,
+    PotentiallyHazardous,
+    /// on the sentry impact monitoring list, shown over potentially hazardous
+    Sentry,
 }
 
+impl HazardClass {
+    const ALL: [HazardClass; 3] = [HazardClass::Normal, HazardClass::PotentiallyHazardous, HazardClass::Sentry];
+
+    fn of(neo_object: &nasa::models::NearEarthObject) -> HazardClass {
+        if neo_object.is_sentry_object {
+            HazardClass::Sentry
+        } else if neo_object.is_potentially_hazardous_asteroid {
+            HazardClass::PotentiallyHazardous
+        } else {
+            HazardClass::Normal
+        }
+    }
+
+    fn color(&self) -> Color {
+        match self {
+            HazardClass::Normal => Color::rgb(1., 1., 0.),
+            HazardClass::PotentiallyHazardous => Color::rgb(1., 0.3, 0.),
+            HazardClass::Sentry => Color::rgb(0.8, 0., 1.),
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            HazardClass::Normal => "Near earth object",
+            HazardClass::PotentiallyHazardous => "Potentially hazardous",
+            HazardClass::Sentry => "Sentry (impact monitoring)",
+        }
+    }
+}
+
+/// Mean estimated diameter in kilometers
+#[derive(Component, Debug)]
+struct Diameter(f64);
+
 #[derive(Default, Debug)]
 struct DateRange {
     start_date: String,
     end_date: String,
 }
 
+/// Request sent from the bevy ECS to tokio to fetch NASA data
+#[derive(Debug)]
+enum DataRequest {
+    Range(RangeRequest),
+    /// Full record of a single object, answered with [`DataResponse::Lookup`]
+    Object { id: String },
+}
+
+#[derive(Debug)]
+struct RangeRequest {
+    start_date: NaiveDate,
+    end_date: NaiveDate,
+    look_up_orbits: bool,
+}
+
+/// Sent back from tokio to the bevy ECS
+enum DataResponse {
+    /// The feed for the requested range, always sent before any lookups
+    Feed(Result<NearEarthObjectResponse, nasa::Error>),
+    /// Full record of an object, including its orbit and every close approach
+    Lookup(nasa::models::NearEarthObject),
+    /// The remaining lookups were given up on
+    LookupsStopped(nasa::Error),
+    /// A single object requested with [`DataRequest::Object`] could not be looked up
+    LookupFailed { id: String, error: nasa::Error },
+}
+
 fn controls_ui(
     mut egui_context: ResMut<EguiContext>,
     mut ui_state: ResMut<UiState>,
     data_request_sender: Res<NearEarthObjectDataRequestSender>,
+    near_earth_object_client: Res<NearEarthObjectClient>,
+    mut simulation_time: ResMut<simulation::SimulationTime>,
+    mut display_settings: ResMut<DisplaySettings>,
     near_earth_objects: Query<(&Transform, &NearEarthObject), Without<camera::PanOrbitCamera>>,
     mut camera: Query<(&mut camera::PanOrbitCamera, &mut Transform)>,
 ) {
     egui::Window::new("Controls").show(egui_context.ctx_mut(), |ui| {
 
         ui.horizontal(|ui| {
-            ui.label("Date: ");
+            ui.label("Start Date: ");
             ui.text_edit_singleline(&mut ui_state.date_range.start_date);
         });
+        ui.horizontal(|ui| {
+            ui.label("End Date: ");
+            ui.text_edit_singleline(&mut ui_state.date_range.end_date);
+        });
 
         ui.horizontal(|ui| {
             let query_button = ui.button("Query");
             if query_button.clicked() {
-                // todo: validate date is valid
-                let date = if ui_state.date_range.start_date.trim().is_empty() {
+                let start_date = if ui_state.date_range.start_date.trim().is_empty() {
                     "2020-01-01"
                 } else {
                     ui_state.date_range.start_date.trim()
                 };
-                // fire off event to query for Nasa data (and possibly recreate NEOs)
+                // default to only querying a single day
+                let end_date = if ui_state.date_range.end_date.trim().is_empty() {
+                    start_date
+                } else {
+                    ui_state.date_range.end_date.trim()
+                };
                 info!("params: date_range={:?}", ui_state.date_range);
-                // NOTE: for now, only support querying data for 1 day
-                if let Err(e) = data_request_sender.0.send(
-                    DateRange{ start_date: date.into(), end_date: date.into() }
-                ) {
-                    error!("Error when trying to send data request {:?}", e)
+                match (NaiveDate::parse_from_str(start_date, "%F"), NaiveDate::parse_from_str(end_date, "%F")) {
+                    (Ok(start_date), Ok(end_date)) if start_date <= end_date => {
+                        // fire off event to query for Nasa data (and possibly recreate NEOs)
+                        let look_up_orbits = ui_state.look_up_orbits;
+                        if let Err(e) = data_request_sender.0.send(DataRequest::Range(RangeRequest { start_date, end_date, look_up_orbits })) {
+                            error!("Error when trying to send data request {:?}", e)
+                        }
+                    },
+                    _ => error!("Invalid date range, expected YYYY-MM-DD with start before end: {:?}", ui_state.date_range),
                 }
             }
+            if let Some(rate_limit) = near_earth_object_client.0.rate_limit() {
+                ui.label(format!("API quota: {}/{}", rate_limit.remaining, rate_limit.limit));
+            }
         });
+        ui.checkbox(&mut ui_state.look_up_orbits, "Look up orbits")
+            .on_hover_text("Uses one api request per object, objects without an orbit fly past in a straight line");
+        if let Some(warning) = &ui_state.warning {
+            ui.colored_label(egui::Color32::YELLOW, warning);
+        }
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            let play_label = if simulation_time.playing { "Pause" } else { "Play" };
+            if ui.button(play_label).clicked() {
+                // start over when playing from the end of the range
+                if !simulation_time.playing && simulation_time.current >= simulation_time.end {
+                    simulation_time.current = simulation_time.start;
+                }
+                simulation_time.playing = !simulation_time.playing;
+            }
+            ui.add(egui::Slider::new(&mut simulation_time.speed, 1.0..=simulation::MAX_SPEED).logarithmic(true).text("x speed"));
+        });
+        let (start, end) = (simulation_time.start, simulation_time.end);
+        ui.add(egui::Slider::new(&mut simulation_time.current, start..=end).show_value(false));
+        if let Some(date_time) = orbit::date_time(simulation_time.current) {
+            ui.label(format!("Time: {} UTC", date_time.format("%F %H:%M")));
+        }
         for (_camera, transform) in camera.iter() {
             ui.horizontal(|ui| {
                 ui.label(format!("Camera Translation: {:?}", transform.translation));
@@ -102,6 +266,15 @@ fn controls_ui(
         }
         
 
+        ui.separator();
+        ui.add(egui::Slider::new(&mut display_settings.size_exaggeration, 1.0..=1_000_000.0).logarithmic(true).text("x size"));
+        ui.checkbox(&mut display_settings.logarithmic_size, "Logarithmic size");
+        for hazard_class in HazardClass::ALL {
+            let color = hazard_class.color();
+            let color = egui::Color32::from_rgb((color.r() * 255.) as u8, (color.g() * 255.) as u8, (color.b() * 255.) as u8);
+            ui.colored_label(color, hazard_class.label());
+        }
+
         ui.separator();
         if ui.button("Reset Camera").clicked() {
             // update camera back to original position
@@ -117,8 +290,8 @@ fn controls_ui(
         }
         for (object_transform,object) in near_earth_objects.iter() {
             ui.horizontal(|ui| {
-                if ui.button(format!("{}", object.0)).on_hover_text("Click to copy").clicked() {
-                    ui.output().copied_text = format!("{}", object.0);
+                if ui.button(object.0.id.as_str()).on_hover_text("Click to copy").clicked() {
+                    ui.output().copied_text = object.0.id.clone();
                 }
                 if ui.button("focus").clicked() {
                     // update camera focus point
@@ -140,57 +313,530 @@ fn controls_ui(
     });
 }
 
+/// Data of the selected object, closing the window clears the selection
+fn inspector_ui(
+    mut commands: Commands,
+    mut egui_context: ResMut<EguiContext>,
+    selected: Query<(Entity, &NearEarthObject, &HazardClass, Option<&CloseApproach>), With<picking::Selected>>,
+) {
+    let (entity, object, hazard_class, shown_approach) = match selected.iter().next() {
+        Some(selected) => selected,
+        None => return,
+    };
+    let object = &object.0;
+    let mut open = true;
+    egui::Window::new(object.name.as_str())
+        // keep the window in place when the selection changes
+        .id(egui::Id::new("inspector"))
+        .open(&mut open)
+        .show(egui_context.ctx_mut(), |ui| {
+            egui::Grid::new("inspector_summary").show(ui, |ui| {
+                ui.label("Id");
+                ui.label(object.id.as_str());
+                ui.end_row();
+                ui.label("Reference id");
+                ui.label(object.neo_reference_id.as_str());
+                ui.end_row();
+                ui.label("Absolute magnitude (H)");
+                ui.label(format!("{:.2}", object.absolute_magnitude_h));
+                ui.end_row();
+                ui.label("Potentially hazardous");
+                ui.label(if object.is_potentially_hazardous_asteroid { "yes" } else { "no" });
+                ui.end_row();
+                ui.label("Sentry object");
+                ui.label(if object.is_sentry_object { "yes" } else { "no" });
+                ui.end_row();
+                ui.label("Shown as");
+                let color = hazard_class.color();
+                ui.colored_label(egui::Color32::from_rgb((color.r() * 255.) as u8, (color.g() * 255.) as u8, (color.b() * 255.) as u8), hazard_class.label());
+                ui.end_row();
+            });
+            ui.hyperlink_to("NASA JPL small body database", object.nasa_jpl_url.as_str());
+
+            ui.separator();
+            ui.label("Estimated diameter");
+            egui::Grid::new("inspector_diameter").striped(true).show(ui, |ui| {
+                let diameters = &object.estimated_diameter;
+                for (unit, diameter) in [("km", &diameters.kilometers), ("m", &diameters.meters), ("mi", &diameters.miles), ("ft", &diameters.feet)] {
+                    ui.label(unit);
+                    ui.label(format!("{:.3} - {:.3}", diameter.estimated_diameter_min, diameter.estimated_diameter_max));
+                    ui.end_row();
+                }
+            });
+
+            ui.separator();
+            ui.label(format!("Close approaches ({})", object.close_approach_data.len()));
+            // the feed only has the approach in the queried range, see look_up_selected
+            if object.orbital_data.is_none() {
+                ui.label("Only the approach in the queried range, the rest load once the object is looked up");
+            }
+            egui::ScrollArea::vertical().max_height(300.).show(ui, |ui| {
+                for (i, event) in object.close_approach_data.iter().enumerate() {
+                    let shown = shown_approach.map_or(false, |shown| shown.0.close_approach_date_full == event.close_approach_date_full);
+                    let title = format!("{} UTC, {}{}", event.close_approach_date_full.format("%F %H:%M"), event.orbiting_body,
+                        if shown { " (shown)" } else { "" });
+                    egui::CollapsingHeader::new(title)
+                        .id_source(("inspector_close_approach", i))
+                        .default_open(shown)
+                        .show(ui, |ui| {
+                            egui::Grid::new(("inspector_close_approach_grid", i)).striped(true).show(ui, |ui| {
+                                let velocity = &event.relative_velocity;
+                                ui.label("Relative velocity");
+                                ui.label(format!("{:.3} km/s\n{:.0} km/h\n{:.0} mph",
+                                    velocity.kilometers_per_second, velocity.kilometers_per_hour, velocity.miles_per_hour));
+                                ui.end_row();
+                                let miss_distance = &event.miss_distance;
+                                ui.label("Miss distance");
+                                ui.label(format!("{:.0} km\n{:.0} mi\n{:.2} lunar\n{:.5} au",
+                                    miss_distance.kilometers, miss_distance.miles, miss_distance.lunar, miss_distance.astronomical));
+                                ui.end_row();
+                            });
+                        });
+                }
+            });
+        });
+    if !open {
+        commands.entity(entity).remove::<picking::Selected>();
+    }
+}
+
+/// The feed leaves out the orbit and every close approach outside the queried range,
+/// look the selected object up so the inspector can show its whole history
+fn look_up_selected(
+    data_request_sender: Res<NearEarthObjectDataRequestSender>,
+    selected: Query<&NearEarthObject, Added<picking::Selected>>,
+) {
+    for object in selected.iter() {
+        // only looked up objects have orbital data
+        if object.0.orbital_data.is_none() {
+            if let Err(e) = data_request_sender.0.send(DataRequest::Object { id: object.0.id.clone() }) {
+                error!("Error when trying to send data request {:?}", e)
+            }
+        }
+    }
+}
+
 #[derive(Component)]
-struct NearEarthObject(String);
+struct NearEarthObject(nasa::models::NearEarthObject);
+
+/// The close approach the object's trajectory is centered on
+#[derive(Component, Debug)]
+struct CloseApproach(nasa::models::CloseApproachEvent);
 
 fn read_new_near_earth_object_data_stream(
     mut data_receiver: ResMut<NearEarthObjectDataReciever>,
+    mut ui_state: ResMut<UiState>,
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
-    existing_near_earth_objects: Query<(Entity, &NearEarthObject)>,
+    mut simulation_time: ResMut<simulation::SimulationTime>,
+    display_settings: Res<DisplaySettings>,
+    mut near_earth_objects: Query<(Entity, &mut NearEarthObject, &CloseApproach, &mut simulation::Trajectory, &mut Transform)>,
 ) {
-    match data_receiver.0.try_recv() {
-        Ok(v) => {
-            info!("New near earth object data! {:?}", v.element_count);
+    loop {
+        match data_receiver.0.try_recv() {
+            Ok(DataResponse::Feed(Err(nasa::Error::RateLimited { .. }))) => {
+                ui_state.warning = Some(String::from("NASA api rate limit reached, try again later"));
+            },
+            Ok(DataResponse::Feed(Err(e))) => {
+                ui_state.warning = Some(format!("Could not load near earth objects: {}", e));
+            },
+            Ok(DataResponse::LookupsStopped(e)) => {
+                ui_state.warning = Some(format!("Stopped looking up orbits: {}", e));
+            },
+            Ok(DataResponse::LookupFailed { id, error }) => {
+                ui_state.warning = Some(format!("Could not look up id={}: {}", id, error));
+            },
+            Ok(DataResponse::Lookup(looked_up)) => {
+                let earth = simulation::earth_at(simulation_time.current);
+                // the same object can be spawned once for each of its close approaches
+                for (_, mut object, close_approach, mut trajectory, mut transform) in near_earth_objects.iter_mut() {
+                    if object.0.id == looked_up.id {
+                        *trajectory = simulation::Trajectory::new(&looked_up, &close_approach.0);
+                        transform.translation = trajectory.position_at(simulation_time.current, &earth);
+                        object.0 = looked_up.clone();
+                    }
+                }
+            },
+            Ok(DataResponse::Feed(Ok(v))) => {
+                info!("New near earth object data! {:?}", v.element_count);
+                ui_state.warning = None;
 
-            // delete the existing objects
-            for (e, _object) in existing_near_earth_objects.iter() {
-                commands.entity(e).despawn();
-            }
+                // delete the existing objects
+                for (e, ..) in near_earth_objects.iter() {
+                    // recursive so the selection halo goes with it
+                    commands.entity(e).despawn_recursive();
+                }
+
+                // play back the loaded days, from the start of the first day to the end of the last one
+                let midnights: Vec<f64> = v.near_earth_objects.keys()
+                    .filter_map(|date| NaiveDate::parse_from_str(date, "%F").ok()?.and_hms_opt(0, 0, 0))
+                    .map(orbit::julian_date)
+                    .collect();
+                if let (Some(first), Some(last)) = (midnights.iter().cloned().reduce(f64::min), midnights.iter().cloned().reduce(f64::max)) {
+                    simulation_time.set_range(first, last + 1.);
+                }
 
-            // todo: send event to spawn asteroids?
-            for (date, neo_objects) in v.near_earth_objects.iter() {
-                info!("date: {} num_objects: {}", date, neo_objects.len());
-                for neo_object in neo_objects {
-                    // todo: calculate radius and distance
-                    // todo: instead of copying the string, convert to f32
-                    let close_approach_date = neo_object.close_approach_data
-                        .first();
-                    if let Some(close_approach_date) = close_approach_date {
-                        let miss_distance = close_approach_date.miss_distance.kilometers.parse::<f32>().ok()
-                        .map(|distance| distance / UNIT_SIZE);
-                        
-                        if let Some(miss_distance) = miss_distance {
+                let earth = simulation::earth_at(simulation_time.current);
+                // every object shares a unit sphere, scaled to its size
+                let mesh = meshes.add(Mesh::from(shape::Icosphere { radius: 1., subdivisions: 10}));
+                let hazard_materials: Vec<(HazardClass, Handle<StandardMaterial>)> = HazardClass::ALL.iter()
+                    .map(|hazard_class| (*hazard_class, materials.add(hazard_class.color().into())))
+                    .collect();
+
+                // todo: send event to spawn asteroids?
+                for (date, neo_objects) in v.near_earth_objects.iter() {
+                    info!("date: {} num_objects: {}", date, neo_objects.len());
+                    for neo_object in neo_objects {
+                        let close_approach = neo_object.close_approach_data
+                            .first();
+                        if let Some(close_approach) = close_approach {
+                            let trajectory = simulation::Trajectory::new(neo_object, close_approach);
+                            let kilometers = &neo_object.estimated_diameter.kilometers;
+                            let diameter = Diameter((kilometers.estimated_diameter_min + kilometers.estimated_diameter_max) / 2.);
+                            let hazard_class = HazardClass::of(neo_object);
+                            let material = hazard_materials.iter()
+                                .find(|(class, _)| *class == hazard_class)
+                                .map(|(_, material)| material.clone())
+                                .unwrap_or_default();
                             commands.spawn_bundle(PbrBundle {
-                                mesh: meshes.add(Mesh::from(shape::Icosphere { radius: 0.1, subdivisions: 10})),
-                                material: materials.add(Color::rgb(1., 1., 0.0).into()),
-                                transform: Transform::from_xyz(miss_distance, 0., 0.),
+                                mesh: mesh.clone(),
+                                material,
+                                transform: Transform::from_translation(trajectory.position_at(simulation_time.current, &earth))
+                                    .with_scale(Vec3::splat(display_settings.radius(diameter.0))),
                                 ..Default::default()
-                            }).insert(NearEarthObject(neo_object.id.clone()));
-                         }
+                            })
+                            .insert(NearEarthObject(neo_object.clone()))
+                            .insert(trajectory)
+                            .insert(diameter)
+                            .insert(hazard_class)
+                            .insert(CloseApproach(close_approach.clone()));
+                        }
                     }
                 }
+                // lookups for the new objects can only find them once the spawn commands have run
+                break;
+            },
+            Err(tokio::sync::mpsc::error::TryRecvError::Empty) => break,
+            Err(e) => {
+                error!("Error when trying to read from data_reciever: {:?}", e);
+                // todo: close reciever?
+                break;
             }
-        },
-        Err(tokio::sync::mpsc::error::TryRecvError::Empty) => (),
-        Err(e) => {
-            error!("Error when trying to read from data_reciever: {:?}", e);
-            // todo: close reciever?
         }
     }
 }
 
+/// Resize the near earth objects when the display settings change
+fn scale_near_earth_objects(
+    display_settings: Res<DisplaySettings>,
+    mut near_earth_objects: Query<(&Diameter, &mut Transform)>,
+) {
+    if !display_settings.is_changed() {
+        return;
+    }
+    for (diameter, mut transform) in near_earth_objects.iter_mut() {
+        transform.scale = Vec3::splat(display_settings.radius(diameter.0));
+    }
+}
+
+mod simulation {
+    use bevy::prelude::*;
+    use rocks::nasa::models::{CloseApproachEvent, NearEarthObject};
+    use rocks::orbit::{self, OrbitalElements};
+
+    use super::UNIT_SIZE;
+
+    /// Fastest playback, a week per second
+    pub const MAX_SPEED: f64 = 7. * 24. * 60. * 60.;
+
+    /// Time shown by the scene as a julian date, limited to the range of the loaded data
+    pub struct SimulationTime {
+        pub current: f64,
+        pub start: f64,
+        pub end: f64,
+        pub playing: bool,
+        /// simulated seconds per real second
+        pub speed: f64,
+    }
+
+    impl Default for SimulationTime {
+        fn default() -> Self {
+            SimulationTime {
+                current: orbit::J2000,
+                start: orbit::J2000,
+                end: orbit::J2000,
+                playing: false,
+                speed: 60. * 60.,
+            }
+        }
+    }
+
+    impl SimulationTime {
+        /// Show a new range, paused at its start
+        pub fn set_range(&mut self, start: f64, end: f64) {
+            self.start = start;
+            self.end = end;
+            self.current = start;
+            self.playing = false;
+        }
+    }
+
+    pub fn advance_simulation_time(time: Res<Time>, mut simulation_time: ResMut<SimulationTime>) {
+        if !simulation_time.playing {
+            return;
+        }
+        let days = time.delta_seconds_f64() * simulation_time.speed / (24. * 60. * 60.);
+        simulation_time.current += days;
+        if simulation_time.current >= simulation_time.end {
+            simulation_time.current = simulation_time.end;
+            simulation_time.playing = false;
+        }
+    }
+
+    /// Path of an object past the earth, positions are computed for the current time as the clock moves
+    #[derive(Component)]
+    pub struct Trajectory {
+        /// julian date of the close approach
+        close_approach_time: f64,
+        /// in scene units
+        miss_distance: f32,
+        /// in scene units per day, for the straight line used without an orbit
+        velocity: f32,
+        /// the object's orbit and how much its positions are scaled by
+        orbit: Option<(OrbitalElements, f32)>,
+    }
+
+    impl Trajectory {
+        /// The object's path comes from propagating its orbit, scaled so that at the close approach it is exactly
+        /// NASA's miss distance away since a two body orbit leaves out the earth's pull. Objects without orbital data
+        /// fly past in a straight line at their relative velocity.
+        pub fn new(neo_object: &NearEarthObject, close_approach: &CloseApproachEvent) -> Trajectory {
+            let close_approach_time = orbit::julian_date(close_approach.close_approach_date_full);
+            let miss_distance = close_approach.miss_distance.kilometers as f32 / UNIT_SIZE;
+            let orbit = neo_object.orbital_data.as_ref()
+                .map(OrbitalElements::from)
+                .and_then(|elements| {
+                    let close_approach_position = elements.geocentric(close_approach_time)
+                        .map_err(|e| warn!("Could not propagate orbit of id={}: {}", neo_object.id, e))
+                        .ok()?;
+                    let distance = ecliptic_to_scene(close_approach_position.position).length();
+                    (distance > 0.).then(|| (elements, miss_distance / distance))
+                });
+            Trajectory {
+                close_approach_time,
+                miss_distance,
+                velocity: close_approach.relative_velocity.kilometers_per_second as f32 * 24. * 60. * 60. / UNIT_SIZE,
+                orbit,
+            }
+        }
+
+        /// Position at the julian date, `earth` is the earth's heliocentric state at the same date (see [`earth_at`])
+        pub fn position_at(&self, julian_date: f64, earth: &orbit::StateVector) -> Vec3 {
+            self.orbit.as_ref()
+                .and_then(|(elements, scale)| {
+                    let heliocentric = elements.heliocentric(julian_date).ok()?;
+                    Some(ecliptic_to_scene((heliocentric - *earth).position) * *scale)
+                })
+                .unwrap_or_else(|| Vec3::X * self.miss_distance + Vec3::Z * self.velocity * (julian_date - self.close_approach_time) as f32)
+        }
+    }
+
+    /// Heliocentric state of the earth, shared by every trajectory at the julian date
+    pub fn earth_at(julian_date: f64) -> orbit::StateVector {
+        OrbitalElements::earth().heliocentric(julian_date).unwrap_or_default()
+    }
+
+    pub fn move_near_earth_objects(
+        simulation_time: Res<SimulationTime>,
+        mut near_earth_objects: Query<(&Trajectory, &mut Transform)>,
+    ) {
+        if !simulation_time.is_changed() {
+            return;
+        }
+        let earth = earth_at(simulation_time.current);
+        for (trajectory, mut transform) in near_earth_objects.iter_mut() {
+            transform.translation = trajectory.position_at(simulation_time.current, &earth);
+        }
+    }
+
+    /// The ecliptic frame has z pointing north, the scene has y up
+    fn ecliptic_to_scene(position: orbit::Vector3) -> Vec3 {
+        Vec3::new(position.x as f32, position.z as f32, -position.y as f32)
+    }
+}
+
+mod picking {
+    use bevy::prelude::*;
+    use bevy_egui::{egui, EguiContext};
+
+    use super::camera::PanOrbitCamera;
+    use super::{CloseApproach, NearEarthObject};
+
+    /// Halo radius relative to the selected object
+    const HALO_SCALE: f32 = 1.4;
+
+    /// Marks the selected near earth object, at most one is selected at a time
+    #[derive(Component, Debug)]
+    pub struct Selected;
+
+    /// Near earth object under the mouse cursor
+    #[derive(Default, Debug)]
+    pub struct Hovered(pub Option<Entity>);
+
+    #[derive(Component)]
+    pub struct SelectionHalo;
+
+    pub struct HaloAssets {
+        mesh: Handle<Mesh>,
+        material: Handle<StandardMaterial>,
+    }
+
+    pub fn load_halo(
+        mut commands: Commands,
+        mut meshes: ResMut<Assets<Mesh>>,
+        mut materials: ResMut<Assets<StandardMaterial>>,
+    ) {
+        commands.insert_resource(HaloAssets {
+            mesh: meshes.add(Mesh::from(shape::Icosphere { radius: 1., subdivisions: 10 })),
+            material: materials.add(StandardMaterial {
+                base_color: Color::rgba(1., 1., 1., 0.25),
+                unlit: true,
+                alpha_mode: AlphaMode::Blend,
+                ..Default::default()
+            }),
+        });
+    }
+
+    /// Cast a ray from the camera through the cursor and keep the closest near earth object it hits
+    pub fn hover_near_earth_objects(
+        windows: Res<Windows>,
+        mut egui_context: ResMut<EguiContext>,
+        cameras: Query<(&Camera, &GlobalTransform), With<PanOrbitCamera>>,
+        near_earth_objects: Query<(Entity, &GlobalTransform), With<NearEarthObject>>,
+        mut hovered: ResMut<Hovered>,
+    ) {
+        let window = match windows.get_primary() {
+            Some(window) => window,
+            None => return,
+        };
+        // the controls window is on top of the scene
+        let cursor = match window.cursor_position() {
+            Some(cursor) if !egui_context.ctx_mut().is_pointer_over_area() => cursor,
+            _ => {
+                hovered.0 = None;
+                return;
+            }
+        };
+
+        let mut closest: Option<(Entity, f32)> = None;
+        for (camera, camera_transform) in cameras.iter() {
+            let (origin, direction) = cursor_ray(window, cursor, camera, camera_transform);
+            for (entity, transform) in near_earth_objects.iter() {
+                // the meshes are unit spheres scaled to their radius
+                if let Some(distance) = ray_sphere_distance(origin, direction, transform.translation, transform.scale.x) {
+                    if closest.map_or(true, |(_, closest_distance)| distance < closest_distance) {
+                        closest = Some((entity, distance));
+                    }
+                }
+            }
+        }
+        let entity = closest.map(|(entity, _)| entity);
+        if hovered.0 != entity {
+            hovered.0 = entity;
+        }
+    }
+
+    /// Left click selects the hovered object, clicking empty space clears the selection
+    pub fn select_near_earth_objects(
+        mut commands: Commands,
+        input_mouse: Res<Input<MouseButton>>,
+        mut egui_context: ResMut<EguiContext>,
+        hovered: Res<Hovered>,
+        selected: Query<Entity, With<Selected>>,
+    ) {
+        if !input_mouse.just_pressed(MouseButton::Left) || egui_context.ctx_mut().is_pointer_over_area() {
+            return;
+        }
+        for entity in selected.iter() {
+            if Some(entity) != hovered.0 {
+                commands.entity(entity).remove::<Selected>();
+            }
+        }
+        if let Some(entity) = hovered.0 {
+            if selected.get(entity).is_err() {
+                commands.entity(entity).insert(Selected);
+            }
+        }
+    }
+
+    /// Keep a halo around the selected object
+    pub fn highlight_selected(
+        mut commands: Commands,
+        halo_assets: Res<HaloAssets>,
+        newly_selected: Query<Entity, Added<Selected>>,
+        unselected: RemovedComponents<Selected>,
+        halos: Query<(Entity, &Parent), With<SelectionHalo>>,
+    ) {
+        for entity in unselected.iter() {
+            for (halo, parent) in halos.iter() {
+                if parent.0 == entity {
+                    commands.entity(halo).despawn();
+                }
+            }
+        }
+        for entity in newly_selected.iter() {
+            commands.entity(entity).with_children(|parent| {
+                parent.spawn_bundle(PbrBundle {
+                    mesh: halo_assets.mesh.clone(),
+                    material: halo_assets.material.clone(),
+                    transform: Transform::from_scale(Vec3::splat(HALO_SCALE)),
+                    ..Default::default()
+                }).insert(SelectionHalo);
+            });
+        }
+    }
+
+    pub fn near_earth_object_tooltip(
+        mut egui_context: ResMut<EguiContext>,
+        hovered: Res<Hovered>,
+        near_earth_objects: Query<(&NearEarthObject, &CloseApproach)>,
+    ) {
+        if let Some((object, close_approach)) = hovered.0.and_then(|entity| near_earth_objects.get(entity).ok()) {
+            egui::show_tooltip_at_pointer(egui_context.ctx_mut(), egui::Id::new("near_earth_object_tooltip"), |ui| {
+                ui.label(object.0.name.as_str());
+                ui.label(format!("Miss distance: {:.0} km", close_approach.0.miss_distance.kilometers));
+            });
+        }
+    }
+
+    /// Origin and direction of the ray from the camera through the cursor position (in window coordinates)
+    fn cursor_ray(window: &Window, cursor: Vec2, camera: &Camera, camera_transform: &GlobalTransform) -> (Vec3, Vec3) {
+        let window_size = Vec2::new(window.width() as f32, window.height() as f32);
+        // window coordinates start at the bottom left, same as normalized device coordinates
+        let ndc = (cursor / window_size) * 2. - Vec2::ONE;
+        let ndc_to_world = camera_transform.compute_matrix() * camera.projection_matrix.inverse();
+        // any depth in front of the camera is on the ray, which starts at the camera for a perspective projection
+        let point = ndc_to_world.project_point3(ndc.extend(0.5));
+        let origin = camera_transform.translation;
+        (origin, (point - origin).normalize())
+    }
+
+    /// Distance along the ray to the first intersection with the sphere, `None` if the ray misses it
+    fn ray_sphere_distance(origin: Vec3, direction: Vec3, center: Vec3, radius: f32) -> Option<f32> {
+        let to_center = center - origin;
+        let closest_approach = to_center.dot(direction);
+        let distance_squared = to_center.length_squared() - closest_approach * closest_approach;
+        let radius_squared = radius * radius;
+        if distance_squared > radius_squared {
+            return None;
+        }
+        let half_chord = (radius_squared - distance_squared).sqrt();
+        let distance = if closest_approach - half_chord >= 0. { closest_approach - half_chord } else { closest_approach + half_chord };
+        (distance >= 0.).then(|| distance)
+    }
+}
+
 mod camera {
     use bevy::prelude::*;
     use bevy::input::mouse::{MouseWheel,MouseMotion};
@@ -319,37 +965,121 @@ mod camera {
     }
 }
 
-struct NearEarthObjectDataReciever(tokio::sync::mpsc::UnboundedReceiver<nasa::models::NearEarthObjectResponse>);
-struct NearEarthObjectDataRequestSender(tokio::sync::mpsc::UnboundedSender<DateRange>);
+struct NearEarthObjectDataReciever(tokio::sync::mpsc::UnboundedReceiver<DataResponse>);
+struct NearEarthObjectDataRequestSender(tokio::sync::mpsc::UnboundedSender<DataRequest>);
+/// Shared with the tokio runtime, used by the ui to show the remaining api quota
+struct NearEarthObjectClient(Arc<nasa::client::NearEarthObjectClient>);
+
+/// The feed doesn't have orbits, look each object up so it can be placed around the earth.
+/// Lookups are cached by the client so querying the same objects again doesn't use up quota.
+async fn look_up_orbits(
+    client: Arc<nasa::client::NearEarthObjectClient>,
+    ids: Vec<String>,
+    response_data_sender: tokio::sync::mpsc::UnboundedSender<DataResponse>,
+) {
+    let num_ids = ids.len();
+    let mut num_orbits = 0;
+    let lookups = client.get_near_earth_objects_by_id(ids);
+    pin_mut!(lookups);
+    while let Some((id, lookup)) = lookups.next().await {
+        let message = match lookup {
+            Ok(object) => {
+                num_orbits += object.orbital_data.is_some() as usize;
+                DataResponse::Lookup(object)
+            },
+            // every other lookup would be refused too
+            Err(e @ nasa::Error::RateLimited { .. }) => {
+                warn!("Stopped looking up orbits after {}/{} objects: {}", num_orbits, num_ids, e);
+                let _ = response_data_sender.send(DataResponse::LookupsStopped(e));
+                return;
+            },
+            Err(e) => {
+                warn!("Could not look up id={}: {}", id, e);
+                continue;
+            },
+        };
+        if let Err(_) = response_data_sender.send(message) {
+            error!("The reciever dropped for response_data");
+            return;
+        }
+    }
+    info!("Found orbital data for {}/{} objects", num_orbits, num_ids);
+}
 
 fn main() -> Result<(), Box<dyn std::error::Error>>{
 
     // initialize API client
-    let mut private_config = Ini::new();
-    private_config.load("config/private.ini").expect("unable to load config from: config/private.ini");
-    let nasa_api_key = private_config.get("topsecrets","NASA_API_KEY").expect("could not find NASA_API_KEY");
-    let near_earth_object_client = nasa::client::NearEarthObjectClient::new(&nasa_api_key);
+    let settings = config::Settings::load(&config::Overrides::default()).unwrap_or_else(|e| panic!("{}", e));
+    let nasa_api_key = settings.nasa_api_key().unwrap_or_else(|e| panic!("{}", e));
+    let near_earth_object_client = nasa::client::NearEarthObjectClient::builder()
+        .api_key(nasa_api_key)
+        .concurrency(4);
+    // cache responses so querying the same dates again doesn't use up quota,
+    // with a database they are kept between runs (and shared with the importer)
+    let near_earth_object_client = match settings.database_url() {
+        Ok(database_url) => match db_util::try_establish_connection(database_url, &db_util::ConnectionOptions::default()) {
+            Ok(connection) => near_earth_object_client.cache(nasa::client::SqliteResponseCache::new(connection)),
+            Err(e) => {
+                // bevy's logging isn't set up yet
+                eprintln!("Could not open database {}, only caching responses in memory: {}", database_url, e);
+                near_earth_object_client.cache(nasa::client::MemoryResponseCache::new())
+            },
+        },
+        Err(_) => near_earth_object_client.cache(nasa::client::MemoryResponseCache::new()),
+    };
+    let near_earth_object_client = Arc::new(near_earth_object_client.build());
+    let tokio_near_earth_object_client = Arc::clone(&near_earth_object_client);
 
     // setup runtime to handle external calls
     // create channel used to communicate between bevy ECS to tokio
-    let (request_data_sender, mut request_data_receiver) = tokio::sync::mpsc::unbounded_channel::<DateRange>();
-    let (response_data_sender, response_data_receiver) = tokio::sync::mpsc::unbounded_channel::<NearEarthObjectResponse>();
+    let (request_data_sender, mut request_data_receiver) = tokio::sync::mpsc::unbounded_channel::<DataRequest>();
+    let (response_data_sender, response_data_receiver) = tokio::sync::mpsc::unbounded_channel::<DataResponse>();
 
     // Create the runtime
     let rt  = Runtime::new()?;
     rt.spawn( async move {
+        // orbit lookups for the last requested range, stopped when another range is requested
+        let mut lookups: Option<tokio::task::JoinHandle<()>> = None;
         loop {
             // listen for data retrieval requests
             match request_data_receiver.recv().await {
-                Some(date_range) =>{
-                    println!("Got message in tokio: {:?}", date_range);
-
-                    if let Ok(response) = near_earth_object_client.get_near_earth_objects(&date_range.start_date, &date_range.end_date).await {
-                        if let Err(_) = response_data_sender.send(response) {
-                            println!("The reciever dropped for response_data");
+                Some(DataRequest::Object { id }) => {
+                    info!("Looking up id={} in tokio", id);
+                    let client = Arc::clone(&tokio_near_earth_object_client);
+                    let response_data_sender = response_data_sender.clone();
+                    tokio::spawn(async move {
+                        let message = match client.get_near_earth_object(&id).await {
+                            Ok(object) => DataResponse::Lookup(object),
+                            Err(error) => DataResponse::LookupFailed { id, error },
+                        };
+                        if let Err(_) = response_data_sender.send(message) {
+                            error!("The reciever dropped for response_data");
                         }
-                    } else {
-                        println!("Error when trying to call api with date_range={:?}", date_range)
+                    });
+                },
+                Some(DataRequest::Range(date_range)) =>{
+                    info!("Got message in tokio: {:?}", date_range);
+                    if let Some(lookups) = lookups.take() {
+                        lookups.abort();
+                    }
+
+                    let response = tokio_near_earth_object_client.get_near_earth_objects_range(date_range.start_date, date_range.end_date).await;
+                    let mut ids = Vec::new();
+                    match &response {
+                        Ok(response) if date_range.look_up_orbits => {
+                            ids = response.near_earth_objects.values().flatten().map(|object| object.id.clone()).collect();
+                            ids.sort();
+                            ids.dedup();
+                        },
+                        Ok(_) => (),
+                        Err(e) => error!("Error when trying to call api with date_range={:?}: {}", date_range, e),
+                    }
+                    // show the feed right away, the orbits are filled in as they're looked up
+                    if let Err(_) = response_data_sender.send(DataResponse::Feed(response)) {
+                        error!("The reciever dropped for response_data");
+                    }
+                    if !ids.is_empty() {
+                        lookups = Some(tokio::spawn(look_up_orbits(Arc::clone(&tokio_near_earth_object_client), ids, response_data_sender.clone())));
                     }
                 },
                 None => ()
@@ -363,6 +1093,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>>{
         .add_plugin(RocksPlugin)
         .insert_resource(NearEarthObjectDataReciever(response_data_receiver))
         .insert_resource(NearEarthObjectDataRequestSender(request_data_sender))
+        .insert_resource(NearEarthObjectClient(near_earth_object_client))
         .run();
     
     Ok(())
@@ -1,50 +1,274 @@
-use rocks;
-use rocks::schema::api_response::dsl;
+use rocks::config;
 use rocks::db_util;
-use rocks::nasa;
+use rocks::nasa::models::NearEarthObject;
+use rocks::store::{NeoStore, SearchFilter};
 
-use diesel::prelude::*;
-use diesel::SqliteConnection;
-use diesel;
-use configparser::ini::Ini;
-use env_logger;
+use std::cmp::Ordering;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+
+use chrono::NaiveDate;
+use clap::{ArgEnum, Parser};
 use env_logger::Env;
+use serde::Serialize;
 
 #[macro_use]
 extern crate log;
 
-use serde_json;
+/// Query the close approaches stored by the importer
+#[derive(Parser,Debug)]
+#[clap(version, about, long_about = None)]
+struct Args {
+    /// first close approach date to include (YYYY-MM-DD)
+    #[clap(long)]
+    start_date: Option<NaiveDate>,
+    /// last close approach date to include (YYYY-MM-DD)
+    #[clap(long)]
+    end_date: Option<NaiveDate>,
+    /// only objects whose largest estimated diameter is at least this many kilometers
+    #[clap(long)]
+    min_diameter_km: Option<f64>,
+    /// only approaches closer than this many kilometers
+    #[clap(long)]
+    max_miss_km: Option<f64>,
+    /// only approaches closer than this many lunar distances
+    #[clap(long)]
+    max_miss_lunar: Option<f64>,
+    /// only potentially hazardous asteroids, or only the ones that aren't with --hazardous=false
+    #[clap(long, min_values = 0, max_values = 1, require_equals = true, default_missing_value = "true")]
+    hazardous: Option<bool>,
+    /// only objects on the sentry impact monitoring list, or only the ones that aren't with --sentry=false
+    #[clap(long, min_values = 0, max_values = 1, require_equals = true, default_missing_value = "true")]
+    sentry: Option<bool>,
+    /// only approaches to this body (ex. Earth, Mars)
+    #[clap(long)]
+    orbiting_body: Option<String>,
+    /// column to sort by
+    #[clap(long, arg_enum, default_value = "close_approach_date")]
+    sort: Field,
+    /// sort in descending order
+    #[clap(long)]
+    desc: bool,
+    #[clap(short, long, arg_enum, default_value = "table")]
+    format: Format,
+    /// write to this file instead of stdout
+    #[clap(short, long, parse(from_os_str))]
+    output: Option<PathBuf>,
+    /// use this database instead of the configured DATABASE_URL
+    #[clap(long)]
+    database_url: Option<String>,
+}
+
+#[derive(ArgEnum,Clone,Copy,Debug)]
+enum Format {
+    Table,
+    Csv,
+    JsonLines,
+    Json,
+}
+
+/// Columns of a [`Row`], named after the fields they sort by
+#[derive(ArgEnum,Clone,Copy,Debug)]
+#[clap(rename_all = "snake_case")]
+enum Field {
+    Id,
+    Name,
+    CloseApproachDate,
+    EstimatedDiameterMinKm,
+    EstimatedDiameterMaxKm,
+    AbsoluteMagnitudeH,
+    IsPotentiallyHazardousAsteroid,
+    IsSentryObject,
+    RelativeVelocityKmS,
+    MissDistanceKm,
+    MissDistanceLunar,
+    OrbitingBody,
+}
+
+/// One close approach of an object
+#[derive(Serialize,Debug)]
+struct Row {
+    id: String,
+    name: String,
+    close_approach_date: String,
+    close_approach_date_full: String,
+    estimated_diameter_min_km: f64,
+    estimated_diameter_max_km: f64,
+    absolute_magnitude_h: f64,
+    is_potentially_hazardous_asteroid: bool,
+    is_sentry_object: bool,
+    relative_velocity_km_s: f64,
+    miss_distance_km: f64,
+    miss_distance_lunar: f64,
+    orbiting_body: String,
+}
+
+impl Row {
+    fn from_object(object: &NearEarthObject) -> Vec<Row> {
+        object.close_approach_data.iter()
+            .map(|event| Row {
+                id: object.id.clone(),
+                name: object.name.clone(),
+                close_approach_date: event.close_approach_date.format("%F").to_string(),
+                close_approach_date_full: event.close_approach_date_full.format("%F %H:%M").to_string(),
+                estimated_diameter_min_km: object.estimated_diameter.kilometers.estimated_diameter_min,
+                estimated_diameter_max_km: object.estimated_diameter.kilometers.estimated_diameter_max,
+                absolute_magnitude_h: object.absolute_magnitude_h,
+                is_potentially_hazardous_asteroid: object.is_potentially_hazardous_asteroid,
+                is_sentry_object: object.is_sentry_object,
+                relative_velocity_km_s: event.relative_velocity.kilometers_per_second,
+                miss_distance_km: event.miss_distance.kilometers,
+                miss_distance_lunar: event.miss_distance.lunar,
+                orbiting_body: event.orbiting_body.clone(),
+            })
+            .collect()
+    }
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
-    info!("Starting up");
+    fn compare(&self, other: &Row, field: Field) -> Ordering {
+        let numbers = |a: f64, b: f64| a.partial_cmp(&b).unwrap_or(Ordering::Equal);
+        match field {
+            Field::Id => self.id.cmp(&other.id),
+            Field::Name => self.name.cmp(&other.name),
+            // the full date has the time of day, so it sorts approaches on the same day as well
+            Field::CloseApproachDate => self.close_approach_date_full.cmp(&other.close_approach_date_full),
+            Field::EstimatedDiameterMinKm => numbers(self.estimated_diameter_min_km, other.estimated_diameter_min_km),
+            Field::EstimatedDiameterMaxKm => numbers(self.estimated_diameter_max_km, other.estimated_diameter_max_km),
+            Field::AbsoluteMagnitudeH => numbers(self.absolute_magnitude_h, other.absolute_magnitude_h),
+            Field::IsPotentiallyHazardousAsteroid => self.is_potentially_hazardous_asteroid.cmp(&other.is_potentially_hazardous_asteroid),
+            Field::IsSentryObject => self.is_sentry_object.cmp(&other.is_sentry_object),
+            Field::RelativeVelocityKmS => numbers(self.relative_velocity_km_s, other.relative_velocity_km_s),
+            Field::MissDistanceKm => numbers(self.miss_distance_km, other.miss_distance_km),
+            Field::MissDistanceLunar => numbers(self.miss_distance_lunar, other.miss_distance_lunar),
+            Field::OrbitingBody => self.orbiting_body.cmp(&other.orbiting_body),
+        }
+    }
+
+    /// Columns shown by the table format, numbers are rounded to something readable
+    fn table_cells(&self) -> Vec<String> {
+        vec![
+            self.id.clone(),
+            self.name.clone(),
+            self.close_approach_date_full.clone(),
+            format!("{:.3}-{:.3}", self.estimated_diameter_min_km, self.estimated_diameter_max_km),
+            format!("{:.2}", self.absolute_magnitude_h),
+            String::from(if self.is_potentially_hazardous_asteroid { "yes" } else { "no" }),
+            String::from(if self.is_sentry_object { "yes" } else { "no" }),
+            format!("{:.2}", self.relative_velocity_km_s),
+            format!("{:.0}", self.miss_distance_km),
+            format!("{:.2}", self.miss_distance_lunar),
+            self.orbiting_body.clone(),
+        ]
+    }
+}
+
+const TABLE_HEADERS: [&str; 11] = ["id", "name", "close approach", "diameter km", "magnitude", "hazardous", "sentry", "velocity km/s", "miss km", "miss lunar", "body"];
+
+fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    // results go to stdout, keep the logs down to problems
+    env_logger::Builder::from_env(Env::default().default_filter_or("warn")).init();
+    let args = Args::parse();
+    info!("{:?}", args);
 
     // read configs
-    let mut private_config = Ini::new();
-    private_config.load("config/private.ini")?;
-    let database_url = private_config.get("topsecrets","DATABASE_URL").expect("could not find DATABASE_URL");
+    let overrides = config::Overrides { database_url: args.database_url.clone(), ..Default::default() };
+    let database_url = match config::Settings::load(&overrides).and_then(|settings| Ok(settings.database_url()?.to_string())) {
+        Ok(database_url) => database_url,
+        Err(e) => {
+            error!("{}", e);
+            std::process::exit(1);
+        }
+    };
 
-    // initialize db connection
-    let connection = db_util::establish_connnection(&database_url);
+    // initialize db connection, only reading so leave the schema and journal mode alone
+    let options = db_util::ConnectionOptions { run_migrations: false, wal: false, ..Default::default() };
+    let connection = match db_util::try_establish_connection(&database_url, &options) {
+        Ok(connection) => connection,
+        Err(e) => {
+            error!("Could not open database {}: {}", database_url, e);
+            std::process::exit(1);
+        }
+    };
+    let store = NeoStore::new(connection);
     info!("Connected to database");
 
-    // Read NASA data from db
-    read_asteroid_api_responses(&connection).await;
-    info!("Finished");
+    let filter = SearchFilter {
+        start_date: args.start_date,
+        end_date: args.end_date,
+        min_diameter_km: args.min_diameter_km,
+        max_miss_distance_km: args.max_miss_km,
+        max_miss_distance_lunar: args.max_miss_lunar,
+        is_potentially_hazardous_asteroid: args.hazardous,
+        is_sentry_object: args.sentry,
+        orbiting_body: args.orbiting_body.clone(),
+    };
+    let mut rows: Vec<Row> = store.search(&filter)?.iter().flat_map(Row::from_object).collect();
+    rows.sort_by(|a, b| a.compare(b, args.sort));
+    if args.desc {
+        rows.reverse();
+    }
+
+    let failures = store.import_failures()?;
+    if !failures.is_empty() {
+        info!("{} responses were rejected by the importer, see the import_failure table", failures.len());
+    }
+
+    let mut output: Box<dyn Write> = match &args.output {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(BufWriter::new(io::stdout())),
+    };
+    write_rows(&mut output, &rows, args.format)?;
+    output.flush()?;
+    info!("Wrote {} close approaches", rows.len());
     Ok(())
 }
 
+fn write_rows(output: &mut dyn Write, rows: &[Row], format: Format) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    match format {
+        Format::Table => write_table(output, rows)?,
+        Format::Csv => {
+            let mut writer = csv::Writer::from_writer(output);
+            for row in rows {
+                writer.serialize(row)?;
+            }
+            writer.flush()?;
+        }
+        Format::JsonLines => {
+            for row in rows {
+                serde_json::to_writer(&mut *output, row)?;
+                writeln!(output)?;
+            }
+        }
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut *output, rows)?;
+            writeln!(output)?;
+        }
+    }
+    Ok(())
+}
 
-async fn read_asteroid_api_responses(connection: &SqliteConnection) {
-    let results = dsl::api_response.load::<rocks::models::ApiResponse>(connection).expect("Could not read all asteroid records.");
-    for record in results {
-        debug!("response={:?}", record);
+fn write_table(output: &mut dyn Write, rows: &[Row]) -> io::Result<()> {
+    let cells: Vec<Vec<String>> = rows.iter().map(Row::table_cells).collect();
+    let mut widths: Vec<usize> = TABLE_HEADERS.iter().map(|header| header.len()).collect();
+    for row in &cells {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
 
-        // map raw responses onto 
-        let asteroid_response = serde_json::from_str::<nasa::models::NearEarthObjectResponse>(&record.response).expect("Could not serialize response");
-        info!("serialized={:?}", asteroid_response);
+    let headers: Vec<String> = TABLE_HEADERS.iter().map(|header| header.to_string()).collect();
+    write_table_line(output, &headers, &widths)?;
+    let separators: Vec<String> = widths.iter().map(|width| "-".repeat(*width)).collect();
+    write_table_line(output, &separators, &widths)?;
+    for row in &cells {
+        write_table_line(output, row, &widths)?;
     }
+    Ok(())
 }
 
-
+fn write_table_line(output: &mut dyn Write, cells: &[String], widths: &[usize]) -> io::Result<()> {
+    let padded: Vec<String> = cells.iter().zip(widths)
+        .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+        .collect();
+    writeln!(output, "{}", padded.join("  ").trim_end())
+}
@@ -0,0 +1,80 @@
+//! Importing feed responses that were saved to disk (ex. from the browser) instead of fetched from the api
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::prelude::*;
+use rocks::nasa::models::NearEarthObjectResponse;
+use rocks::store::NeoStore;
+
+use crate::validate::{self, Rejection};
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+/// Import every file, directories import the `.json` files directly inside them.
+/// Files that can't be imported are logged and skipped, the error reports how many failed.
+/// Files that aren't valid responses are also recorded as import failures.
+pub fn import_files(store: &NeoStore, paths: &[PathBuf]) -> Result<(), Error> {
+    let files = collect_files(paths)?;
+    let mut failed = 0;
+    for path in &files {
+        match import_file(store, path) {
+            Ok(num_objects) => info!("Imported {} near earth objects from {}", num_objects, path.display()),
+            Err(e) => {
+                error!("Could not import {}: {}", path.display(), e);
+                failed += 1;
+            }
+        }
+    }
+    info!("Imported {}/{} files", files.len() - failed, files.len());
+    if failed > 0 {
+        return Err(format!("{} of {} files could not be imported", failed, files.len()).into());
+    }
+    Ok(())
+}
+
+fn collect_files(paths: &[PathBuf]) -> Result<Vec<PathBuf>, Error> {
+    let mut files = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            let mut entries = Vec::new();
+            for entry in fs::read_dir(path)? {
+                let entry_path = entry?.path();
+                if entry_path.is_file() && entry_path.extension().is_some_and(|extension| extension == "json") {
+                    entries.push(entry_path);
+                }
+            }
+            entries.sort();
+            files.extend(entries);
+        } else {
+            files.push(path.clone());
+        }
+    }
+    Ok(files)
+}
+
+fn import_file(store: &NeoStore, path: &Path) -> Result<usize, Error> {
+    let contents = fs::read(path)?;
+    let (response, (start_date, end_date)) = match parse_response(&contents) {
+        Ok(parsed) => parsed,
+        Err(reason) => {
+            let rejection = Rejection::new(reason, Some(String::from_utf8_lossy(&contents).into_owned()));
+            rejection.record(store, &path.display().to_string(), None, None)?;
+            return Err(rejection.into());
+        }
+    };
+    info!("Importing {} for start_date={} end_date={}", path.display(), start_date, end_date);
+
+    // the file was saved some time after the response was fetched, so its age is the best guess we have
+    let fetched_at = fs::metadata(path)?.modified().ok()
+        .map(|modified| DateTime::<Utc>::from(modified).naive_utc());
+    Ok(store.upsert_response_fetched_at(&start_date.format("%F").to_string(), &end_date.format("%F").to_string(), response, fetched_at)?)
+}
+
+/// The file contents as a response body and the window it's for
+fn parse_response(contents: &[u8]) -> Result<(&str, (NaiveDate, NaiveDate)), String> {
+    let body = std::str::from_utf8(contents).map_err(|e| format!("could not decode response: {}", e))?;
+    let response: NearEarthObjectResponse = serde_json::from_str(body).map_err(|e| format!("could not decode response: {}", e))?;
+    let window = validate::response_window(&response, None)?;
+    Ok((body, window))
+}
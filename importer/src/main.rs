@@ -1,14 +1,21 @@
 extern crate rocks;
+use rocks::config;
 use rocks::db_util;
+use rocks::nasa;
+use rocks::nasa::client::{feed_windows, NearEarthObjectClient, RateLimitPolicy, RetryPolicy, MAX_FEED_DAYS};
+use rocks::nasa::models::NearEarthObjectResponse;
+use rocks::store::{NeoStore, StoredWindow};
+use validate::Rejection;
+
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use futures::future;
+use futures::stream::{self, StreamExt};
+use hyper::StatusCode;
 
-use diesel::prelude::*;
-use diesel::SqliteConnection;
-use diesel;
-use hyper::Client;
-use hyper::body;
-use hyper::client::connect::HttpConnector;
-use hyper_tls::HttpsConnector;
-use configparser::ini::Ini;
 use env_logger;
 use env_logger::Env;
 use chrono::prelude::*;
@@ -16,15 +23,75 @@ use chrono::prelude::*;
 #[macro_use]
 extern crate log;
 
-use clap::Parser;
+use clap::{AppSettings, Parser, Subcommand};
+
+mod import_files;
+mod validate;
+
+/// `import_failure` source of responses fetched from the feed api
+const API_SOURCE: &str = "api";
 
-#[derive(Parser,Debug)]
+#[derive(Parser)]
 #[clap(version, about, long_about = None)]
+#[clap(setting = AppSettings::SubcommandsNegateReqs, setting = AppSettings::ArgsNegateSubcommands)]
 struct Args {
+    #[clap(subcommand)]
+    command: Option<Command>,
+    /// first day to import (YYYY-MM-DD)
+    #[clap(short, long, required = true)]
+    start: Option<NaiveDate>,
+    /// last day to import (YYYY-MM-DD, inclusive), defaults to a single window or to today with --fill-gaps
     #[clap(short, long)]
-    start_date: String,
-    #[clap(short, long, default_value_t = 1)]
-    num_batches: u8
+    end: Option<NaiveDate>,
+    /// number of windows to fetch at the same time, all requests share the same rate limit
+    #[clap(short, long, default_value_t = 1, validator = parse_concurrency)]
+    concurrency: usize,
+    /// only print the windows that would be fetched
+    #[clap(long)]
+    dry_run: bool,
+    /// only fetch the date windows between start and end that aren't stored yet
+    #[clap(long)]
+    fill_gaps: bool,
+    /// use this database instead of the configured DATABASE_URL
+    #[clap(long, global = true)]
+    database_url: Option<String>,
+    /// use this api key instead of the configured NASA_API_KEY
+    #[clap(long)]
+    nasa_api_key: Option<String>,
+}
+
+// written out so the api key never ends up in the logs
+impl fmt::Debug for Args {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Args")
+            .field("command", &self.command)
+            .field("start", &self.start)
+            .field("end", &self.end)
+            .field("concurrency", &self.concurrency)
+            .field("dry_run", &self.dry_run)
+            .field("fill_gaps", &self.fill_gaps)
+            .field("database_url", &self.database_url)
+            .field("nasa_api_key", &self.nasa_api_key.as_ref().map(|_| "<redacted>"))
+            .finish()
+    }
+}
+
+#[derive(Subcommand,Debug)]
+enum Command {
+    /// Import feed responses saved as json files instead of fetching them
+    ImportFiles {
+        /// response files, or directories of .json response files
+        #[clap(required = true, parse(from_os_str))]
+        paths: Vec<PathBuf>,
+    },
+}
+
+fn parse_concurrency(value: &str) -> Result<(), String> {
+    match value.parse::<usize>() {
+        Ok(0) => Err(String::from("must be at least 1")),
+        Ok(_) => Ok(()),
+        Err(e) => Err(e.to_string()),
+    }
 }
 
 #[tokio::main]
@@ -34,71 +101,250 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     let args = Args::parse();
     info!("{:?}", args);
 
-    // validate start date parameter 
-    let mut start_date = NaiveDate::parse_from_str(&args.start_date, "%F")?;
-
     info!("Starting up");
     // read configs
-    let mut private_config = Ini::new();
-    private_config.load("config/private.ini")?;
-    let nasa_api_key = private_config.get("topsecrets","NASA_API_KEY").expect("could not find NASA_API_KEY");
-    let database_url = private_config.get("topsecrets","DATABASE_URL").expect("could not find DATABASE_URL");
+    let overrides = config::Overrides {
+        nasa_api_key: args.nasa_api_key.clone(),
+        database_url: args.database_url.clone(),
+    };
+    let settings = setting(config::Settings::load(&overrides));
+    let database_url = setting(settings.database_url());
 
-    // initialize db connection
-    let connection = db_util::establish_connnection(&database_url);
-    info!("Connected to database");
+    if let Some(Command::ImportFiles { paths }) = &args.command {
+        let store = open_store(database_url, &db_util::ConnectionOptions::default());
+        import_files::import_files(&store, paths)?;
+        info!("Completed importer");
+        return Ok(());
+    }
+
+    // validate date parameters, start is required without a subcommand
+    let start_date = args.start.expect("start is required");
+    if let Some(end_date) = args.end {
+        if end_date < start_date {
+            error!("End date {} is before start date {}", end_date, start_date);
+            std::process::exit(1);
+        }
+    }
 
-    // initialize API client
-    let https = HttpsConnector::new();
-    let client =  Client::builder().build::<_, hyper::Body>(https);
+    // only gap filling needs to know what's stored, a plain dry run never opens (or creates) the database
+    let mut store = None;
+    let windows = if args.fill_gaps {
+        let end_date = args.end.unwrap_or_else(|| Utc::now().naive_utc().date());
+        let stored_windows = if args.dry_run {
+            // dry runs read what's stored without migrating the database or switching it to WAL
+            let options = db_util::ConnectionOptions { run_migrations: false, wal: false, ..Default::default() };
+            open_store(database_url, &options).stored_windows()?
+        } else {
+            store.insert(open_store(database_url, &db_util::ConnectionOptions::default())).stored_windows()?
+        };
+        let windows = missing_windows(start_date, end_date, &stored_windows);
+        info!("Found {} missing windows between start_date={} end_date={}", windows.len(), start_date, end_date);
+        windows
+    } else {
+        let end_date = args.end.unwrap_or(start_date + chrono::Duration::days(MAX_FEED_DAYS));
+        feed_windows(start_date, end_date)
+    };
+
+    if args.dry_run {
+        for (start_date, end_date) in &windows {
+            println!("{} {}", start_date.format("%F"), end_date.format("%F"));
+        }
+        info!("Dry run, would have fetched {} windows", windows.len());
+        return Ok(());
+    }
+    let store = store.unwrap_or_else(|| open_store(database_url, &db_util::ConnectionOptions::default()));
 
-    let num_batches = args.num_batches;
-    if num_batches > 0 {
-        for _batch in 1..=num_batches {
-            let end_date = start_date + chrono::Duration::days(7);
+    // initialize API client, pause when we run out of quota instead of failing the whole import,
+    // whether we knew the quota was used up or NASA answered with a 429
+    let mut retryable_statuses = RetryPolicy::default().retryable_statuses;
+    retryable_statuses.push(StatusCode::TOO_MANY_REQUESTS);
+    let client = NearEarthObjectClient::builder()
+        .api_key(setting(settings.nasa_api_key()))
+        .rate_limit_policy(RateLimitPolicy::Wait(Duration::from_secs(10 * 60)))
+        .retry_policy(RetryPolicy {
+            max_attempts: 5,
+            rate_limited_delay: Duration::from_secs(10 * 60),
+            retryable_statuses,
+            ..RetryPolicy::default()
+        })
+        .build();
+
+    // fetch up to `concurrency` windows at once, the connection isn't shared so responses are saved one at a time as they arrive.
+    // After a rejection no new windows are started, the ones already being fetched are still saved.
+    let client = &client;
+    let rejected = AtomicBool::new(false);
+    let mut responses = stream::iter(windows)
+        .take_while(|_| future::ready(!rejected.load(Ordering::Relaxed)))
+        .map(|(start_date, end_date)| async move {
             let start_date_format = start_date.format("%F").to_string();
             let end_date_format = end_date.format("%F").to_string();
             info!("Importing for start_date={} end_date={}", start_date, end_date);
 
-            // Retrieve NASA data
-            let asteroid_data = retrieve_asteroid_data(&client, &start_date_format, &end_date_format, &nasa_api_key).await?;
-            debug!("asteroid_data: {:?}", asteroid_data);
+            // Retrieve NASA data, only responses for the window we asked for are stored
+            let asteroid_data = retrieve_asteroid_data(client, &start_date_format, &end_date_format).await
+                .map_err(Rejection::from)
+                .and_then(|(asteroid_data, body)| match validate::response_window(&asteroid_data, Some((start_date, end_date))) {
+                    Ok(_) => Ok(body),
+                    Err(reason) => Err(Rejection::new(reason, Some(body))),
+                });
+            (start_date_format, end_date_format, asteroid_data)
+        })
+        .buffer_unordered(args.concurrency);
 
-            // Load NASA data onto db
-            load_asteroid_api_response(&connection, &start_date_format, &end_date_format, &asteroid_data).await;
+    let mut first_rejection = None;
+    while let Some((start_date_format, end_date_format, asteroid_data)) = responses.next().await {
+        match asteroid_data {
+            Ok(body) => {
+                debug!("asteroid_data: {}", body);
 
-            // update start_date, add one because response is inclusive
-            start_date = end_date + chrono::Duration::days(1);
+                // Load NASA data onto db
+                load_asteroid_api_response(&store, &start_date_format, &end_date_format, &body).await?;
+            }
+            Err(rejection) => {
+                // keep what NASA sent back out of api_response, and stop before burning through the rest of the quota
+                error!("Rejected response for start_date={} end_date={}: {}", start_date_format, end_date_format, rejection);
+                rejection.record(&store, API_SOURCE, Some(&start_date_format), Some(&end_date_format))?;
+                rejected.store(true, Ordering::Relaxed);
+                first_rejection.get_or_insert(rejection);
+            }
         }
     }
+    if let Some(rejection) = first_rejection {
+        return Err(rejection.into());
+    }
     info!("Completed importer");
     Ok(())
 }
 
-async fn retrieve_asteroid_data(client: &Client<HttpsConnector<HttpConnector>>, start_date: &str, end_date: &str, api_key: &str) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
-    let asteroid_uri = format!("https://api.nasa.gov/neo/rest/v1/feed?start_date={}&end_date={}&api_key={}", start_date, end_date, api_key).parse()?;
-    info!("Making API call to {:?}", asteroid_uri);
-    let resp = client.get(asteroid_uri).await?;
-    trace!("response: {:?}", resp);
+/// Connect to the database with the options (by default bringing its schema up to date), exits if it can't be opened
+fn open_store(database_url: &str, options: &db_util::ConnectionOptions) -> NeoStore {
+    let connection = match db_util::try_establish_connection(database_url, options) {
+        Ok(connection) => connection,
+        Err(e) => {
+            error!("Could not open database {}: {}", database_url, e);
+            std::process::exit(1);
+        }
+    };
+    info!("Connected to database");
+    NeoStore::new(connection)
+}
+
+/// Log a missing or invalid setting and exit, the error says which setting and where to set it
+fn setting<T>(result: Result<T, config::Error>) -> T {
+    result.unwrap_or_else(|e| {
+        error!("{}", e);
+        std::process::exit(1);
+    })
+}
 
-    // todo: validate http status
+/// Windows (split to what the feed accepts) between the dates that aren't covered by a complete stored window
+fn missing_windows(start_date: NaiveDate, end_date: NaiveDate, stored_windows: &[StoredWindow]) -> Vec<(NaiveDate, NaiveDate)> {
+    let mut covered: Vec<(NaiveDate, NaiveDate)> = stored_windows.iter()
+        .filter(|window| window.is_complete())
+        .map(|window| (window.start_date, window.end_date))
+        .collect();
+    covered.sort();
 
-    let body_bytes = body::to_bytes(resp.into_body()).await?;
-    let body_string: String = String::from_utf8(body_bytes.to_vec()).expect("Could not convert response body to string");
-    debug!("body_string: {:?}", body_string);
+    let mut gaps = Vec::new();
+    // first date that isn't known to be covered
+    let mut cursor = start_date;
+    for (covered_start, covered_end) in covered {
+        if cursor > end_date || covered_start > end_date {
+            break;
+        }
+        if covered_end < cursor {
+            continue;
+        }
+        if covered_start > cursor {
+            gaps.push((cursor, covered_start - chrono::Duration::days(1)));
+        }
+        cursor = covered_end + chrono::Duration::days(1);
+    }
+    if cursor <= end_date {
+        gaps.push((cursor, end_date));
+    }
 
-    Ok(body_string)
+    gaps.into_iter()
+        .flat_map(|(gap_start, gap_end)| feed_windows(gap_start, gap_end))
+        .collect()
 }
 
+/// The decoded response along with the body NASA sent, which is what gets stored
+async fn retrieve_asteroid_data(client: &NearEarthObjectClient, start_date: &str, end_date: &str) -> Result<(NearEarthObjectResponse, String), nasa::Error> {
+    info!("Making API call for start_date={} end_date={}", start_date, end_date);
+    let response = client.get_near_earth_objects_with_body(start_date, end_date).await?;
+    if let Some(rate_limit) = client.rate_limit() {
+        info!("NASA api quota remaining={}/{}", rate_limit.remaining, rate_limit.limit);
+    }
+    Ok(response)
+}
 
-async fn load_asteroid_api_response(connection: &SqliteConnection, start_date: &str, end_date: &str, response: &str) {
-   info!("Saving asteroid response for start_date={} end_date={}", start_date, end_date); 
-    use rocks::schema::api_response;
-    let new_api_response = rocks::models::NewApiResponse {
-        start_date,
-        end_date,
-        response
-    };
-    diesel::insert_into(api_response::table).values(&new_api_response).execute(connection).expect("Unable to insert API response");
+
+async fn load_asteroid_api_response(store: &NeoStore, start_date: &str, end_date: &str, response: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    info!("Saving asteroid response for start_date={} end_date={}", start_date, end_date);
+    let num_objects = store.upsert_response(start_date, end_date, response)?;
+    info!("Saved {} near earth objects for start_date={} end_date={}", num_objects, start_date, end_date);
+    Ok(())
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn api_key_is_not_logged() {
+        let args = Args::parse_from(["importer", "--start", "2022-01-08", "--nasa-api-key", "secret-key"]);
+        let logged = format!("{:?}", args);
+        assert!(!logged.contains("secret-key"));
+        assert!(logged.contains("nasa_api_key: Some(\"<redacted>\")"));
+    }
+
+    /// Parse a `YYYY-MM-DD` date, shared with the other importer tests
+    pub(crate) fn date(date: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(date, "%F").unwrap()
+    }
+
+    /// A window fetched long after it was over
+    fn stored(start_date: &str, end_date: &str) -> StoredWindow {
+        StoredWindow { start_date: date(start_date), end_date: date(end_date), fetched_at: date("2026-01-01").and_hms_opt(0, 0, 0) }
+    }
+
+    fn windows(windows: &[(&str, &str)]) -> Vec<(NaiveDate, NaiveDate)> {
+        windows.iter().map(|(start_date, end_date)| (date(start_date), date(end_date))).collect()
+    }
+
+    #[test]
+    fn nothing_stored_is_all_missing() {
+        assert_eq!(missing_windows(date("2022-01-01"), date("2022-01-20"), &[]), feed_windows(date("2022-01-01"), date("2022-01-20")));
+    }
+
+    #[test]
+    fn overlapping_windows_are_covered() {
+        let stored_windows = [stored("2022-01-05", "2022-01-12"), stored("2022-01-03", "2022-01-10")];
+        assert_eq!(missing_windows(date("2022-01-01"), date("2022-01-20"), &stored_windows),
+            windows(&[("2022-01-01", "2022-01-02"), ("2022-01-13", "2022-01-20")]));
+    }
+
+    #[test]
+    fn adjacent_windows_leave_no_gap() {
+        let stored_windows = [stored("2022-01-01", "2022-01-08"), stored("2022-01-09", "2022-01-16")];
+        assert_eq!(missing_windows(date("2022-01-01"), date("2022-01-20"), &stored_windows), windows(&[("2022-01-17", "2022-01-20")]));
+        assert!(missing_windows(date("2022-01-02"), date("2022-01-15"), &stored_windows).is_empty());
+    }
+
+    #[test]
+    fn incomplete_windows_are_fetched_again() {
+        let incomplete = StoredWindow { fetched_at: date("2022-01-05").and_hms_opt(12, 0, 0), ..stored("2022-01-01", "2022-01-08") };
+        let complete = stored("2022-01-09", "2022-01-16");
+        assert!(!incomplete.is_complete());
+        assert_eq!(missing_windows(date("2022-01-01"), date("2022-01-16"), &[incomplete, complete]), windows(&[("2022-01-01", "2022-01-08")]));
+    }
+
+    #[test]
+    fn windows_outside_the_range_are_ignored() {
+        let stored_windows = [stored("2021-12-01", "2021-12-08"), stored("2021-12-28", "2022-01-04"), stored("2022-02-01", "2022-02-08")];
+        // the gap is longer than a feed window, so it's split
+        assert_eq!(missing_windows(date("2022-01-01"), date("2022-01-20"), &stored_windows),
+            windows(&[("2022-01-05", "2022-01-12"), ("2022-01-13", "2022-01-20")]));
+    }
+}
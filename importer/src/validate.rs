@@ -0,0 +1,203 @@
+//! Checks a response has to pass before it's stored, rejected responses are kept in `import_failure`
+
+use std::fmt;
+
+use chrono::prelude::*;
+use rocks::db_util;
+use rocks::models::NewImportFailure;
+use rocks::nasa;
+use rocks::nasa::client::MAX_FEED_DAYS;
+use rocks::nasa::models::NearEarthObjectResponse;
+use rocks::store::NeoStore;
+
+/// Why a response wasn't stored, along with what NASA sent back when there was a response
+#[derive(Debug)]
+pub struct Rejection {
+    pub reason: String,
+    pub status: Option<i32>,
+    pub body: Option<String>,
+}
+
+impl Rejection {
+    pub fn new(reason: String, body: Option<String>) -> Self {
+        Self { reason, status: None, body }
+    }
+
+    /// Save the rejection in the `import_failure` table, `source` is where the response came from
+    pub fn record(&self, store: &NeoStore, source: &str, start_date: Option<&str>, end_date: Option<&str>) -> Result<(), db_util::Error> {
+        store.record_failure(&NewImportFailure {
+            source,
+            start_date,
+            end_date,
+            reason: &self.reason,
+            status: self.status,
+            body: self.body.as_deref(),
+            failed_at: Utc::now().naive_utc(),
+        })
+    }
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.reason)
+    }
+}
+
+impl std::error::Error for Rejection {}
+
+impl From<nasa::Error> for Rejection {
+    fn from(e: nasa::Error) -> Self {
+        match e {
+            // the body is kept separately, so leave it out of the reason
+            nasa::Error::Status { status, body } => Self {
+                reason: format!("unexpected status {}", status),
+                status: Some(status.as_u16() as i32),
+                body: Some(body),
+            },
+            nasa::Error::RateLimited { body, .. } => Self {
+                reason: String::from("rate limited by NASA api"),
+                status: Some(429),
+                body: Some(body),
+            },
+            nasa::Error::Json { path, source, body } => Self {
+                reason: format!("could not decode response at `{}`: {}", path, source),
+                status: None,
+                body: Some(body),
+            },
+            e => Self::new(e.to_string(), None),
+        }
+    }
+}
+
+/// Check the response is consistent and return the date window it covers.
+/// The window comes from `links.self`, falling back to the dates in `near_earth_objects` when the link has no dates.
+/// With an `expected` window the response has to be for exactly those dates.
+pub fn response_window(response: &NearEarthObjectResponse, expected: Option<(NaiveDate, NaiveDate)>) -> Result<(NaiveDate, NaiveDate), String> {
+    let mut dates = Vec::new();
+    for date in response.near_earth_objects.keys() {
+        let date = NaiveDate::parse_from_str(date, "%F")
+            .map_err(|_| format!("near_earth_objects key {:?} is not a date", date))?;
+        dates.push(date);
+    }
+    dates.sort();
+
+    let num_objects: usize = response.near_earth_objects.values().map(Vec::len).sum();
+    if num_objects != response.element_count {
+        return Err(format!("element_count is {} but the response has {} near earth objects", response.element_count, num_objects));
+    }
+
+    let linked_window = response.links.self_link.as_deref().and_then(linked_window);
+    let window = match (linked_window, dates.first(), dates.last()) {
+        (Some((start_date, end_date)), Some(first), Some(last)) => {
+            if *first < start_date || *last > end_date {
+                return Err(format!("near_earth_objects has dates {} to {} outside of the links.self window {} to {}", first, last, start_date, end_date));
+            }
+            (start_date, end_date)
+        }
+        (Some(window), _, _) => window,
+        (None, Some(first), Some(last)) => (*first, *last),
+        _ => return Err(String::from("can't tell which dates the response is for, it has no links.self dates or near_earth_objects")),
+    };
+    if window.1 < window.0 {
+        return Err(format!("end_date {} is before start_date {}", window.1, window.0));
+    }
+    if let Some((start_date, end_date)) = expected {
+        // without a link the object dates only have to fall inside the window, some days have no approaches
+        let matches = match linked_window {
+            Some(_) => window == (start_date, end_date),
+            None => window.0 >= start_date && window.1 <= end_date,
+        };
+        if !matches {
+            return Err(format!("response is for {} to {} instead of {} to {}", window.0, window.1, start_date, end_date));
+        }
+        return Ok((start_date, end_date));
+    }
+    Ok(window)
+}
+
+/// `start_date` and `end_date` of a feed url, the api defaults the end date to a week after the start
+fn linked_window(link: &str) -> Option<(NaiveDate, NaiveDate)> {
+    let (_, query) = link.split_once('?')?;
+    let param = |name: &str| query.split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == name)
+        .and_then(|(_, value)| NaiveDate::parse_from_str(value, "%F").ok());
+    let start_date = param("start_date")?;
+    let end_date = param("end_date").unwrap_or(start_date + chrono::Duration::days(MAX_FEED_DAYS));
+    Some((start_date, end_date))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::tests::date;
+
+    const FEED_URL: &str = "http://api.nasa.gov/neo/rest/v1/feed";
+
+    /// A response with no objects under each of the dates, the window checks only look at the dates
+    fn feed_response(self_link: Option<String>, element_count: usize, dates: &[&str]) -> NearEarthObjectResponse {
+        let near_earth_objects: serde_json::Map<String, serde_json::Value> = dates.iter()
+            .map(|date| (date.to_string(), serde_json::json!([])))
+            .collect();
+        serde_json::from_value(serde_json::json!({
+            "links": {"self": self_link},
+            "element_count": element_count,
+            "near_earth_objects": near_earth_objects,
+        })).unwrap()
+    }
+
+    fn link(query: &str) -> Option<String> {
+        Some(format!("{}?{}", FEED_URL, query))
+    }
+
+    #[test]
+    fn window_comes_from_the_link() {
+        let response = feed_response(link("start_date=2022-01-08&end_date=2022-01-10&detailed=false&api_key=DEMO_KEY"), 0, &["2022-01-08", "2022-01-09"]);
+        assert_eq!(response_window(&response, None), Ok((date("2022-01-08"), date("2022-01-10"))));
+        assert_eq!(response_window(&response, Some((date("2022-01-08"), date("2022-01-10")))), Ok((date("2022-01-08"), date("2022-01-10"))));
+    }
+
+    #[test]
+    fn element_count_has_to_match() {
+        let response = feed_response(link("start_date=2022-01-08&end_date=2022-01-08"), 3, &["2022-01-08"]);
+        let reason = response_window(&response, None).unwrap_err();
+        assert!(reason.contains("element_count is 3"), "{}", reason);
+    }
+
+    #[test]
+    fn dates_have_to_be_inside_the_link() {
+        let response = feed_response(link("start_date=2022-01-08&end_date=2022-01-09"), 0, &["2022-01-08", "2022-01-10"]);
+        let reason = response_window(&response, None).unwrap_err();
+        assert!(reason.contains("outside of the links.self window"), "{}", reason);
+
+        let response = feed_response(link("start_date=2022-01-08&end_date=2022-01-09"), 0, &["2022-01-08", "not a date"]);
+        assert!(response_window(&response, None).is_err());
+    }
+
+    #[test]
+    fn missing_end_date_is_a_week_after_start() {
+        assert_eq!(linked_window(&format!("{}?start_date=2022-01-08&api_key=DEMO_KEY", FEED_URL)), Some((date("2022-01-08"), date("2022-01-15"))));
+        assert_eq!(linked_window(&format!("{}?end_date=2022-01-08", FEED_URL)), None);
+        assert_eq!(linked_window(FEED_URL), None);
+
+        let response = feed_response(link("start_date=2022-01-08"), 0, &["2022-01-08", "2022-01-15"]);
+        assert_eq!(response_window(&response, None), Ok((date("2022-01-08"), date("2022-01-15"))));
+    }
+
+    #[test]
+    fn expected_window_has_to_match() {
+        let response = feed_response(link("start_date=2022-01-08&end_date=2022-01-10"), 0, &["2022-01-08"]);
+        let reason = response_window(&response, Some((date("2022-01-08"), date("2022-01-15")))).unwrap_err();
+        assert_eq!(reason, "response is for 2022-01-08 to 2022-01-10 instead of 2022-01-08 to 2022-01-15");
+
+        // without a link the dates only have to fall inside the expected window
+        let response = feed_response(None, 0, &["2022-01-09", "2022-01-10"]);
+        assert_eq!(response_window(&response, None), Ok((date("2022-01-09"), date("2022-01-10"))));
+        assert_eq!(response_window(&response, Some((date("2022-01-08"), date("2022-01-15")))), Ok((date("2022-01-08"), date("2022-01-15"))));
+        assert!(response_window(&response, Some((date("2022-01-10"), date("2022-01-15")))).is_err());
+
+        let response = feed_response(None, 0, &[]);
+        assert!(response_window(&response, Some((date("2022-01-08"), date("2022-01-15")))).is_err());
+    }
+}
@@ -0,0 +1,11 @@
+-- This file should undo anything in `up.sql`
+CREATE TABLE api_response_backup (
+	id INTEGER PRIMARY KEY ASC NOT NULL,
+	start_date TEXT NOT NULL,
+        end_date TEXT NOT NULL,
+	response TEXT NOT NULL,
+	UNIQUE(start_date, end_date) ON CONFLICT REPLACE
+);
+INSERT INTO api_response_backup SELECT id, start_date, end_date, response FROM api_response;
+DROP TABLE api_response;
+ALTER TABLE api_response_backup RENAME TO api_response;
@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TABLE api_response ADD COLUMN fetched_at TIMESTAMP;
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX close_approach_date_index;
+DROP TABLE close_approach;
+DROP TABLE near_earth_object;
@@ -0,0 +1,31 @@
+-- Your SQL goes here
+CREATE TABLE near_earth_object (
+	id TEXT PRIMARY KEY NOT NULL,
+	neo_reference_id TEXT NOT NULL,
+	name TEXT NOT NULL,
+	nasa_jpl_url TEXT NOT NULL,
+	absolute_magnitude_h DOUBLE NOT NULL,
+	estimated_diameter_min_km DOUBLE NOT NULL,
+	estimated_diameter_max_km DOUBLE NOT NULL,
+	is_potentially_hazardous_asteroid BOOLEAN NOT NULL,
+	is_sentry_object BOOLEAN NOT NULL
+);
+
+CREATE TABLE close_approach (
+	id INTEGER PRIMARY KEY ASC NOT NULL,
+	near_earth_object_id TEXT NOT NULL REFERENCES near_earth_object(id),
+	close_approach_date DATE NOT NULL,
+	close_approach_date_full TIMESTAMP NOT NULL,
+	epoch_date_close_approach BIGINT NOT NULL,
+	relative_velocity_kilometers_per_second DOUBLE NOT NULL,
+	relative_velocity_kilometers_per_hour DOUBLE NOT NULL,
+	relative_velocity_miles_per_hour DOUBLE NOT NULL,
+	miss_distance_astronomical DOUBLE NOT NULL,
+	miss_distance_lunar DOUBLE NOT NULL,
+	miss_distance_kilometers DOUBLE NOT NULL,
+	miss_distance_miles DOUBLE NOT NULL,
+	orbiting_body TEXT NOT NULL,
+	UNIQUE(near_earth_object_id, epoch_date_close_approach, orbiting_body) ON CONFLICT REPLACE
+);
+
+CREATE INDEX close_approach_date_index ON close_approach(close_approach_date);
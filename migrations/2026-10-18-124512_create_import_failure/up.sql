@@ -0,0 +1,11 @@
+-- Your SQL goes here
+CREATE TABLE import_failure (
+	id INTEGER PRIMARY KEY ASC NOT NULL,
+	source TEXT NOT NULL,
+	start_date TEXT,
+	end_date TEXT,
+	reason TEXT NOT NULL,
+	status INTEGER,
+	body TEXT,
+	failed_at TIMESTAMP NOT NULL
+);
@@ -0,0 +1,230 @@
+//! Settings shared by the binaries, layered from (lowest to highest priority)
+//! `config/default.ini`, `config/private.ini`, `ROCKS_*` environment variables and command line overrides.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use configparser::ini::Ini;
+
+/// Directory the config files are read from, relative to where the binaries are run (the project root)
+pub const CONFIG_DIR: &str = "config";
+/// Checked in, values every install can share
+pub const DEFAULT_CONFIG_FILE: &str = "default.ini";
+/// Ignored by git, api keys and local paths
+pub const PRIVATE_CONFIG_FILE: &str = "private.ini";
+/// Section of the config files the settings are read from
+pub const SECTION: &str = "topsecrets";
+
+/// The settings that can be configured
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    NasaApiKey,
+    DatabaseUrl,
+}
+
+impl Key {
+    /// Name of the key in the config files
+    pub fn name(&self) -> &'static str {
+        match self {
+            Key::NasaApiKey => "NASA_API_KEY",
+            Key::DatabaseUrl => "DATABASE_URL",
+        }
+    }
+
+    /// Environment variable that overrides the config files
+    pub fn env_var(&self) -> &'static str {
+        match self {
+            Key::NasaApiKey => "ROCKS_NASA_API_KEY",
+            Key::DatabaseUrl => "ROCKS_DATABASE_URL",
+        }
+    }
+}
+
+/// Where a setting was read from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    File(PathBuf),
+    Env(&'static str),
+    CommandLine,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::File(path) => write!(f, "{}", path.display()),
+            Source::Env(name) => write!(f, "environment variable {}", name),
+            Source::CommandLine => write!(f, "command line"),
+        }
+    }
+}
+
+/// Errors from loading or validating [`Settings`]
+#[derive(Debug)]
+pub enum Error {
+    /// A config file exists but could not be read or parsed
+    File { path: PathBuf, message: String },
+    /// No source set a setting the binary needs
+    Missing { key: Key },
+    /// A source set a setting to a value that can't be used
+    Invalid { key: Key, source: Source, reason: String },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::File { path, message } => write!(f, "could not read config file {}: {}", path.display(), message),
+            Error::Missing { key } => write!(f, "missing setting {}, set it in the [{}] section of {}/{} or with the {} environment variable",
+                key.name(), SECTION, CONFIG_DIR, PRIVATE_CONFIG_FILE, key.env_var()),
+            Error::Invalid { key, source, reason } => write!(f, "invalid setting {} from {}: {}", key.name(), source, reason),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Settings given on the command line, they win over every other source
+#[derive(Debug, Clone, Default)]
+pub struct Overrides {
+    pub nasa_api_key: Option<String>,
+    pub database_url: Option<String>,
+}
+
+/// A setting along with where it came from
+#[derive(Debug, Clone)]
+pub struct Setting {
+    pub value: String,
+    pub source: Source,
+}
+
+/// Merged settings, use the accessors to get a setting that has to be there
+#[derive(Debug, Clone, Default)]
+pub struct Settings {
+    pub nasa_api_key: Option<Setting>,
+    pub database_url: Option<Setting>,
+}
+
+impl Settings {
+    /// Load the settings from [`CONFIG_DIR`], the environment and `overrides`
+    pub fn load(overrides: &Overrides) -> Result<Settings, Error> {
+        Self::load_from(Path::new(CONFIG_DIR), overrides)
+    }
+
+    /// Same as [`Settings::load`] with the config files read from `config_dir`. Config files that don't exist are skipped.
+    pub fn load_from(config_dir: &Path, overrides: &Overrides) -> Result<Settings, Error> {
+        Self::load_with(config_dir, |name| std::env::var(name).ok(), overrides)
+    }
+
+    fn load_with(config_dir: &Path, env: impl Fn(&str) -> Option<String>, overrides: &Overrides) -> Result<Settings, Error> {
+        let mut settings = Settings::default();
+        for file in [DEFAULT_CONFIG_FILE, PRIVATE_CONFIG_FILE] {
+            let path = config_dir.join(file);
+            if !path.exists() {
+                debug!("Skipping missing config file {}", path.display());
+                continue;
+            }
+            let mut ini = Ini::new();
+            ini.load(&path).map_err(|message| Error::File { path: path.clone(), message })?;
+            for key in [Key::NasaApiKey, Key::DatabaseUrl] {
+                if let Some(value) = ini.get(SECTION, key.name()) {
+                    settings.set(key, value, Source::File(path.clone()))?;
+                }
+            }
+        }
+        for key in [Key::NasaApiKey, Key::DatabaseUrl] {
+            if let Some(value) = env(key.env_var()) {
+                settings.set(key, value, Source::Env(key.env_var()))?;
+            }
+        }
+        if let Some(value) = &overrides.nasa_api_key {
+            settings.set(Key::NasaApiKey, value.clone(), Source::CommandLine)?;
+        }
+        if let Some(value) = &overrides.database_url {
+            settings.set(Key::DatabaseUrl, value.clone(), Source::CommandLine)?;
+        }
+        Ok(settings)
+    }
+
+    pub fn nasa_api_key(&self) -> Result<&str, Error> {
+        self.require(Key::NasaApiKey)
+    }
+
+    pub fn database_url(&self) -> Result<&str, Error> {
+        self.require(Key::DatabaseUrl)
+    }
+
+    /// The setting and where it came from, `None` if no source set it
+    pub fn get(&self, key: Key) -> Option<&Setting> {
+        match key {
+            Key::NasaApiKey => self.nasa_api_key.as_ref(),
+            Key::DatabaseUrl => self.database_url.as_ref(),
+        }
+    }
+
+    fn require(&self, key: Key) -> Result<&str, Error> {
+        self.get(key)
+            .map(|setting| setting.value.as_str())
+            .ok_or(Error::Missing { key })
+    }
+
+    /// Validate and override the setting
+    fn set(&mut self, key: Key, value: String, source: Source) -> Result<(), Error> {
+        let value = value.trim().to_string();
+        if value.is_empty() {
+            return Err(Error::Invalid { key, source, reason: String::from("value is empty") });
+        }
+        if key == Key::NasaApiKey && value.contains(char::is_whitespace) {
+            return Err(Error::Invalid { key, source, reason: String::from("api keys can't contain whitespace") });
+        }
+        let setting = Some(Setting { value, source });
+        match key {
+            Key::NasaApiKey => self.nasa_api_key = setting,
+            Key::DatabaseUrl => self.database_url = setting,
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    fn config_dir(name: &str, default: &str, private: Option<&str>) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rocks-config-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(DEFAULT_CONFIG_FILE), default).unwrap();
+        if let Some(private) = private {
+            fs::write(dir.join(PRIVATE_CONFIG_FILE), private).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn later_sources_override_earlier_ones() {
+        let dir = config_dir("layers", "[topsecrets]\nDATABASE_URL = default.db\nNASA_API_KEY = DEMO_KEY\n", Some("[topsecrets]\nDATABASE_URL = private.db\n"));
+        let env = |name: &str| (name == "ROCKS_NASA_API_KEY").then(|| String::from("env-key"));
+
+        let settings = Settings::load_with(&dir, env, &Overrides::default()).unwrap();
+        assert_eq!(settings.database_url().unwrap(), "private.db");
+        assert_eq!(settings.database_url.as_ref().unwrap().source, Source::File(dir.join(PRIVATE_CONFIG_FILE)));
+        assert_eq!(settings.nasa_api_key().unwrap(), "env-key");
+
+        let overrides = Overrides { database_url: Some(String::from("cli.db")), ..Default::default() };
+        let settings = Settings::load_with(&dir, env, &overrides).unwrap();
+        assert_eq!(settings.database_url().unwrap(), "cli.db");
+        assert_eq!(settings.database_url.unwrap().source, Source::CommandLine);
+    }
+
+    #[test]
+    fn errors_name_the_key_and_source() {
+        let dir = config_dir("errors", "", None);
+        let settings = Settings::load_with(&dir, |_| None, &Overrides::default()).unwrap();
+        let error = settings.nasa_api_key().unwrap_err().to_string();
+        assert!(error.contains("NASA_API_KEY") && error.contains("ROCKS_NASA_API_KEY"), "{}", error);
+
+        let env = |name: &str| (name == "ROCKS_DATABASE_URL").then(|| String::from(" "));
+        let error = Settings::load_with(&dir, env, &Overrides::default()).unwrap_err().to_string();
+        assert_eq!(error, "invalid setting DATABASE_URL from environment variable ROCKS_DATABASE_URL: value is empty");
+    }
+}
@@ -1,6 +1,339 @@
+use std::fmt;
+use std::path::Path;
+use std::time::Duration;
+
 use diesel::prelude::*;
 use diesel::SqliteConnection;
+use diesel_migrations::{MigrationConnection, RunMigrationsError};
+
+use crate::models::{ApiResponse, NewCloseApproach, NewNearEarthObject};
+use crate::nasa::models::NearEarthObjectResponse;
+use crate::schema::{api_response, close_approach, near_earth_object};
+
+/// Errors from working with the database
+#[derive(Debug)]
+pub enum Error {
+    Connection(diesel::ConnectionError),
+    Query(diesel::result::Error),
+    /// A stored response could not be read as a [`NearEarthObjectResponse`]
+    Json(serde_json::Error),
+    Migration(RunMigrationsError),
+    /// The database was migrated by a newer version of rocks, we don't know what its tables look like
+    SchemaTooNew { database: String, binary: String },
+    /// The database needs migrations that weren't run because [`ConnectionOptions::run_migrations`] is off,
+    /// `database` is `None` if it was never migrated
+    SchemaOutdated { database: Option<String>, binary: String },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Connection(e) => write!(f, "could not connect to database: {}", e),
+            Error::Query(e) => write!(f, "database query failed: {}", e),
+            Error::Json(e) => write!(f, "could not decode stored response: {}", e),
+            Error::Migration(e) => write!(f, "could not run database migrations: {}", e),
+            Error::SchemaTooNew { database, binary } => write!(f, "database schema version {} is newer than the latest version {} this binary supports", database, binary),
+            Error::SchemaOutdated { database: Some(database), binary } => write!(f, "database schema version {} is older than version {} this binary needs, run the importer to migrate it", database, binary),
+            Error::SchemaOutdated { database: None, binary } => write!(f, "database has no schema, version {} is needed, run the importer to create it", binary),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Connection(e) => Some(e),
+            Error::Query(e) => Some(e),
+            Error::Json(e) => Some(e),
+            Error::Migration(e) => Some(e),
+            Error::SchemaTooNew { .. } | Error::SchemaOutdated { .. } => None,
+        }
+    }
+}
+
+impl From<diesel::ConnectionError> for Error {
+    fn from(e: diesel::ConnectionError) -> Self {
+        Error::Connection(e)
+    }
+}
+
+impl From<diesel::result::Error> for Error {
+    fn from(e: diesel::result::Error) -> Self {
+        Error::Query(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Json(e)
+    }
+}
 
+impl From<RunMigrationsError> for Error {
+    fn from(e: RunMigrationsError) -> Self {
+        Error::Migration(e)
+    }
+}
+
+// embeds the sql files from the project root `migrations/` directory
+embed_migrations!("../migrations");
+
+/// Version of the newest migration in `migrations/`, update this when adding a migration
+pub const SCHEMA_VERSION: &str = "20261018124512";
+
+/// Migration that created the `near_earth_object` and `close_approach` tables, responses stored
+/// before it ran are projected into them by [`run_migrations`]
+const PROJECTION_VERSION: &str = "20261018101544";
+
+/// Settings applied by [`try_establish_connection`] after connecting
+#[derive(Debug, Clone)]
+pub struct ConnectionOptions {
+    /// bring the schema up to date with [`run_migrations`]. When off the database isn't created or changed,
+    /// it has to already exist with the schema at [`SCHEMA_VERSION`].
+    pub run_migrations: bool,
+    /// switch file databases to WAL mode so readers don't block the importer, the mode is saved in the database file
+    pub wal: bool,
+    /// sqlite doesn't enforce `REFERENCES` unless this is turned on for the connection
+    pub foreign_keys: bool,
+    /// how long to wait on a database locked by another connection before failing
+    pub busy_timeout: Duration,
+}
+
+impl Default for ConnectionOptions {
+    fn default() -> Self {
+        Self {
+            run_migrations: true,
+            wal: true,
+            foreign_keys: false,
+            busy_timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Connects with the default [`ConnectionOptions`], panics if the database can't be opened or migrated
 pub fn establish_connnection(database_url: &str) -> SqliteConnection {
-    SqliteConnection::establish(database_url).unwrap_or_else(|_| panic!("Error connecting to {}", database_url))
+    try_establish_connection(database_url, &ConnectionOptions::default())
+        .unwrap_or_else(|e| panic!("Error connecting to {}: {}", database_url, e))
+}
+
+/// Connect to a sqlite database, `database_url` can be a path, `file:<path>`, `sqlite://<path>` or `:memory:`.
+pub fn try_establish_connection(database_url: &str, options: &ConnectionOptions) -> Result<SqliteConnection, Error> {
+    let path = database_path(database_url);
+    // sqlite creates missing files on connect
+    if !options.run_migrations && path != ":memory:" && !Path::new(path).exists() {
+        return Err(Error::Connection(diesel::ConnectionError::BadConnection(format!("{} does not exist", path))));
+    }
+    let connection = SqliteConnection::establish(path)?;
+    // set before switching to WAL, switching has to wait for other connections to let go of the database
+    connection.execute(&format!("PRAGMA busy_timeout = {};", options.busy_timeout.as_millis()))?;
+    if options.wal && path != ":memory:" {
+        connection.execute("PRAGMA journal_mode = WAL;")?;
+    }
+    connection.execute(&format!("PRAGMA foreign_keys = {};", if options.foreign_keys { "ON" } else { "OFF" }))?;
+    if options.run_migrations {
+        run_migrations(&connection)?;
+    } else {
+        check_schema_version(&connection)?;
+    }
+    Ok(connection)
+}
+
+/// sqlite only understands uri filenames when built with SQLITE_USE_URI, so strip the scheme off
+fn database_path(database_url: &str) -> &str {
+    let database_url = database_url.trim();
+    database_url.strip_prefix("sqlite://")
+        .or_else(|| database_url.strip_prefix("file://"))
+        .or_else(|| database_url.strip_prefix("file:"))
+        .unwrap_or(database_url)
+}
+
+/// Run any embedded migrations the database hasn't seen yet and return the resulting schema version.
+/// Refuses to touch a database that has migrations newer than [`SCHEMA_VERSION`].
+pub fn run_migrations(connection: &SqliteConnection) -> Result<String, Error> {
+    diesel_migrations::setup_database(connection)?;
+    let previous_version = connection.latest_run_migration_version()?;
+    // versions are timestamps, so they sort as strings
+    if let Some(version) = &previous_version {
+        if version.as_str() > SCHEMA_VERSION {
+            return Err(Error::SchemaTooNew { database: version.clone(), binary: String::from(SCHEMA_VERSION) });
+        }
+    }
+    embedded_migrations::run(connection)?;
+    if previous_version.is_some_and(|version| version.as_str() < PROJECTION_VERSION) {
+        let num_objects = project_stored_responses(connection)?;
+        info!("Projected {} objects from responses stored before schema version {}", num_objects, PROJECTION_VERSION);
+    }
+    let version = schema_version(connection)?.unwrap_or_default();
+    info!("Database schema version {}", version);
+    Ok(version)
+}
+
+/// Version of the newest migration that has been run, `None` for a database that was never migrated
+pub fn schema_version(connection: &SqliteConnection) -> Result<Option<String>, Error> {
+    // the migrations table is only created by the first migration
+    let has_migrations_table = diesel::select(diesel::dsl::sql::<diesel::sql_types::Bool>(
+        "EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = '__diesel_schema_migrations')"
+    )).get_result::<bool>(connection)?;
+    if !has_migrations_table {
+        return Ok(None);
+    }
+    Ok(connection.latest_run_migration_version()?)
+}
+
+/// Make sure the database is at exactly [`SCHEMA_VERSION`] without migrating it
+pub fn check_schema_version(connection: &SqliteConnection) -> Result<String, Error> {
+    match schema_version(connection)? {
+        Some(version) if version == SCHEMA_VERSION => Ok(version),
+        // versions are timestamps, so they sort as strings
+        Some(version) if version.as_str() > SCHEMA_VERSION => Err(Error::SchemaTooNew { database: version, binary: String::from(SCHEMA_VERSION) }),
+        version => Err(Error::SchemaOutdated { database: version, binary: String::from(SCHEMA_VERSION) }),
+    }
+}
+
+/// Project a stored `api_response` row into the `near_earth_object` and `close_approach` tables.
+/// Running it again for the same row updates the existing records instead of duplicating them.
+/// Returns the number of objects projected.
+pub fn project_api_response(connection: &SqliteConnection, record: &ApiResponse) -> Result<usize, Error> {
+    let response = serde_json::from_str::<NearEarthObjectResponse>(&record.response)?;
+    project_response(connection, &response)
+}
+
+/// Project every stored `api_response` row, rows that can't be decoded are skipped with a warning.
+/// Returns the number of objects projected.
+pub fn project_stored_responses(connection: &SqliteConnection) -> Result<usize, Error> {
+    let records = api_response::table.load::<ApiResponse>(connection)?;
+    let mut num_objects = 0;
+    for record in &records {
+        match project_api_response(connection, record) {
+            Ok(projected) => num_objects += projected,
+            Err(Error::Json(e)) => warn!("Skipping stored response {} for {} to {}: {}", record.id, record.start_date, record.end_date, e),
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(num_objects)
+}
+
+/// Same as [`project_api_response`] for a response that was already decoded
+pub fn project_response(connection: &SqliteConnection, response: &NearEarthObjectResponse) -> Result<usize, Error> {
+    connection.transaction::<_, Error, _>(|| {
+        let mut num_objects = 0;
+        for object in response.near_earth_objects.values().flatten() {
+            let new_object = NewNearEarthObject::from(object);
+            // not using replace, replacing deletes the row that close approaches point at
+            let updated = diesel::update(near_earth_object::table.find(object.id.as_str()))
+                .set(&new_object)
+                .execute(connection)?;
+            if updated == 0 {
+                diesel::insert_into(near_earth_object::table).values(&new_object).execute(connection)?;
+            }
+
+            let new_close_approaches: Vec<NewCloseApproach> = object.close_approach_data.iter()
+                .map(|event| NewCloseApproach::new(&object.id, event))
+                .collect();
+            // close approaches are unique per object, epoch and orbiting body
+            diesel::replace_into(close_approach::table).values(&new_close_approaches).execute(connection)?;
+            num_objects += 1;
+        }
+        Ok(num_objects)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    #[test]
+    fn schema_version_is_the_newest_migration() {
+        let migrations_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../migrations");
+        // diesel versions are the digits of the directory name before the first `_`
+        let newest = fs::read_dir(migrations_dir).unwrap()
+            .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+            .filter_map(|name| Some(name.split('_').next()?.replace('-', "")))
+            .max()
+            .unwrap();
+        assert_eq!(SCHEMA_VERSION, newest);
+
+        let connection = SqliteConnection::establish(":memory:").unwrap();
+        assert_eq!(run_migrations(&connection).unwrap(), SCHEMA_VERSION);
+        assert_eq!(schema_version(&connection).unwrap().as_deref(), Some(SCHEMA_VERSION));
+    }
+
+    #[test]
+    fn newer_schema_is_not_migrated() {
+        let connection = SqliteConnection::establish(":memory:").unwrap();
+        run_migrations(&connection).unwrap();
+        connection.execute("INSERT INTO __diesel_schema_migrations (version) VALUES ('99990101000000');").unwrap();
+
+        match run_migrations(&connection) {
+            Err(Error::SchemaTooNew { database, binary }) => {
+                assert_eq!(database, "99990101000000");
+                assert_eq!(binary, SCHEMA_VERSION);
+            },
+            result => panic!("expected schema too new, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn existing_schema_is_checked_instead_of_migrated() {
+        let dir = std::env::temp_dir().join(format!("rocks-db-util-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("existing.db");
+        let _ = fs::remove_file(&path);
+        let database_url = path.to_str().unwrap();
+        let options = ConnectionOptions { run_migrations: false, wal: false, ..Default::default() };
+
+        // missing databases aren't created
+        assert!(matches!(try_establish_connection(database_url, &options), Err(Error::Connection(_))));
+        assert!(!path.exists());
+
+        let connection = SqliteConnection::establish(database_url).unwrap();
+        match try_establish_connection(database_url, &options) {
+            Err(Error::SchemaOutdated { database: None, .. }) => (),
+            result => panic!("expected schema outdated, got {:?}", result.map(|_| ())),
+        }
+        run_migrations(&connection).unwrap();
+        try_establish_connection(database_url, &options).unwrap();
+        let journal_mode = diesel::select(diesel::dsl::sql::<diesel::sql_types::Text>("journal_mode FROM pragma_journal_mode"))
+            .get_result::<String>(&connection)
+            .unwrap();
+        assert_eq!(journal_mode, "delete");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn responses_stored_before_projection_are_projected() {
+        let connection = SqliteConnection::establish(":memory:").unwrap();
+        run_migrations(&connection).unwrap();
+        let migrations_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../migrations");
+        while schema_version(&connection).unwrap().unwrap().as_str() >= PROJECTION_VERSION {
+            diesel_migrations::revert_latest_migration_in_directory(&connection, &migrations_dir).unwrap();
+        }
+
+        let response = format!(r#"{{
+            "links": {{}},
+            "element_count": 1,
+            "near_earth_objects": {{"2022-01-08": [{}]}}
+        }}"#, crate::nasa::models::tests::object_json("1"));
+        diesel::insert_into(api_response::table)
+            .values(&vec![
+                (api_response::start_date.eq("2022-01-08"), api_response::end_date.eq("2022-01-08"), api_response::response.eq(response)),
+                (api_response::start_date.eq("2022-01-09"), api_response::end_date.eq("2022-01-09"), api_response::response.eq(String::from("not json"))),
+            ])
+            .execute(&connection)
+            .unwrap();
+
+        assert_eq!(run_migrations(&connection).unwrap(), SCHEMA_VERSION);
+        let ids = near_earth_object::table.select(near_earth_object::id).load::<String>(&connection).unwrap();
+        assert_eq!(ids, vec!["1"]);
+        let num_approaches: i64 = close_approach::table.count().get_result(&connection).unwrap();
+        assert_eq!(num_approaches, 1);
+
+        // already projected responses aren't projected again on later runs
+        diesel::delete(near_earth_object::table).execute(&connection).unwrap();
+        run_migrations(&connection).unwrap();
+        let num_objects: i64 = near_earth_object::table.count().get_result(&connection).unwrap();
+        assert_eq!(num_objects, 0);
+    }
 }
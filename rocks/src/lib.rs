@@ -1,10 +1,17 @@
 #[macro_use]
 extern crate diesel;
+#[macro_use]
+extern crate log;
+#[macro_use]
+extern crate diesel_migrations;
 
 pub mod schema;
 pub mod models;
 pub mod db_util;
 pub mod nasa;
+pub mod store;
+pub mod config;
+pub mod orbit;
 
 pub fn helloworld() {
     println!("helloworld")
@@ -1,9 +1,14 @@
+use chrono::{NaiveDate, NaiveDateTime};
+
+use super::nasa;
+
 #[derive(Queryable,Debug)]
 pub struct ApiResponse {
     pub id: i32,
     pub start_date: String,
     pub end_date: String,
-    pub response: String
+    pub response: String,
+    pub fetched_at: Option<NaiveDateTime>
 }
 
 use super::schema::api_response;
@@ -12,5 +17,131 @@ use super::schema::api_response;
 pub struct NewApiResponse<'a> {
     pub start_date: &'a str,
     pub end_date: &'a str,
-    pub response: &'a str 
+    pub response: &'a str,
+    pub fetched_at: Option<NaiveDateTime>
+}
+
+#[derive(Queryable,Debug)]
+pub struct NearEarthObject {
+    pub id: String,
+    pub neo_reference_id: String,
+    pub name: String,
+    pub nasa_jpl_url: String,
+    pub absolute_magnitude_h: f64,
+    pub estimated_diameter_min_km: f64,
+    pub estimated_diameter_max_km: f64,
+    pub is_potentially_hazardous_asteroid: bool,
+    pub is_sentry_object: bool
+}
+
+use super::schema::near_earth_object;
+#[derive(Insertable,AsChangeset)]
+#[table_name="near_earth_object"]
+pub struct NewNearEarthObject<'a> {
+    pub id: &'a str,
+    pub neo_reference_id: &'a str,
+    pub name: &'a str,
+    pub nasa_jpl_url: &'a str,
+    pub absolute_magnitude_h: f64,
+    pub estimated_diameter_min_km: f64,
+    pub estimated_diameter_max_km: f64,
+    pub is_potentially_hazardous_asteroid: bool,
+    pub is_sentry_object: bool
+}
+
+impl<'a> From<&'a nasa::models::NearEarthObject> for NewNearEarthObject<'a> {
+    fn from(object: &'a nasa::models::NearEarthObject) -> Self {
+        Self {
+            id: &object.id,
+            neo_reference_id: &object.neo_reference_id,
+            name: &object.name,
+            nasa_jpl_url: &object.nasa_jpl_url,
+            absolute_magnitude_h: object.absolute_magnitude_h,
+            estimated_diameter_min_km: object.estimated_diameter.kilometers.estimated_diameter_min,
+            estimated_diameter_max_km: object.estimated_diameter.kilometers.estimated_diameter_max,
+            is_potentially_hazardous_asteroid: object.is_potentially_hazardous_asteroid,
+            is_sentry_object: object.is_sentry_object
+        }
+    }
+}
+
+#[derive(Queryable,Debug)]
+pub struct CloseApproach {
+    pub id: i32,
+    pub near_earth_object_id: String,
+    pub close_approach_date: NaiveDate,
+    pub close_approach_date_full: NaiveDateTime,
+    pub epoch_date_close_approach: i64,
+    pub relative_velocity_kilometers_per_second: f64,
+    pub relative_velocity_kilometers_per_hour: f64,
+    pub relative_velocity_miles_per_hour: f64,
+    pub miss_distance_astronomical: f64,
+    pub miss_distance_lunar: f64,
+    pub miss_distance_kilometers: f64,
+    pub miss_distance_miles: f64,
+    pub orbiting_body: String
+}
+
+use super::schema::close_approach;
+#[derive(Insertable)]
+#[table_name="close_approach"]
+pub struct NewCloseApproach<'a> {
+    pub near_earth_object_id: &'a str,
+    pub close_approach_date: NaiveDate,
+    pub close_approach_date_full: NaiveDateTime,
+    pub epoch_date_close_approach: i64,
+    pub relative_velocity_kilometers_per_second: f64,
+    pub relative_velocity_kilometers_per_hour: f64,
+    pub relative_velocity_miles_per_hour: f64,
+    pub miss_distance_astronomical: f64,
+    pub miss_distance_lunar: f64,
+    pub miss_distance_kilometers: f64,
+    pub miss_distance_miles: f64,
+    pub orbiting_body: &'a str
+}
+
+impl<'a> NewCloseApproach<'a> {
+    pub fn new(near_earth_object_id: &'a str, event: &'a nasa::models::CloseApproachEvent) -> Self {
+        Self {
+            near_earth_object_id,
+            close_approach_date: event.close_approach_date,
+            close_approach_date_full: event.close_approach_date_full,
+            epoch_date_close_approach: event.epoch_date_close_approach,
+            relative_velocity_kilometers_per_second: event.relative_velocity.kilometers_per_second,
+            relative_velocity_kilometers_per_hour: event.relative_velocity.kilometers_per_hour,
+            relative_velocity_miles_per_hour: event.relative_velocity.miles_per_hour,
+            miss_distance_astronomical: event.miss_distance.astronomical,
+            miss_distance_lunar: event.miss_distance.lunar,
+            miss_distance_kilometers: event.miss_distance.kilometers,
+            miss_distance_miles: event.miss_distance.miles,
+            orbiting_body: &event.orbiting_body
+        }
+    }
+}
+
+#[derive(Queryable,Debug)]
+pub struct ImportFailure {
+    pub id: i32,
+    pub source: String,
+    pub start_date: Option<String>,
+    pub end_date: Option<String>,
+    pub reason: String,
+    pub status: Option<i32>,
+    pub body: Option<String>,
+    pub failed_at: NaiveDateTime
+}
+
+use super::schema::import_failure;
+/// A response that was rejected instead of stored in `api_response`,
+/// `source` is where it came from (ex. the request path or the file it was read from)
+#[derive(Insertable)]
+#[table_name="import_failure"]
+pub struct NewImportFailure<'a> {
+    pub source: &'a str,
+    pub start_date: Option<&'a str>,
+    pub end_date: Option<&'a str>,
+    pub reason: &'a str,
+    pub status: Option<i32>,
+    pub body: Option<&'a str>,
+    pub failed_at: NaiveDateTime
 }
@@ -1,95 +1,1531 @@
+use std::fmt;
+
+use hyper::StatusCode;
+
+/// Errors returned by the [`client::NearEarthObjectClient`]
+#[derive(Debug)]
+pub enum Error {
+    /// The request uri could not be built from the base url and parameters
+    Uri(hyper::http::uri::InvalidUri),
+    /// The request could not be built (ex. the configured user agent is not a valid header value)
+    Request(hyper::http::Error),
+    /// The request failed before NASA sent back a response (connection reset, dns, tls...)
+    Http(hyper::Error),
+    /// NASA did not respond within the configured timeout
+    Timeout,
+    /// NASA responded with a non success status code, `body` is whatever NASA sent back
+    Status { status: StatusCode, body: String },
+    /// The api key has used up its quota, either NASA responded with 429 or the client refused to send
+    /// the request because the last known quota was zero
+    RateLimited { rate_limit: Option<client::RateLimit>, body: String },
+    /// The response body could not be decoded, `path` points at the field that failed (ex. `near_earth_objects.2022-01-08[0].id`)
+    /// and `body` is whatever NASA sent back
+    Json { path: String, source: serde_json::Error, body: String },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Uri(e) => write!(f, "invalid request uri: {}", e),
+            Error::Request(e) => write!(f, "invalid request: {}", e),
+            Error::Http(e) => write!(f, "http request failed: {}", e),
+            Error::Timeout => write!(f, "request timed out"),
+            Error::Status { status, body } => write!(f, "unexpected status {}: {}", status, body),
+            Error::RateLimited { rate_limit: Some(rate_limit), body } => write!(f, "rate limited by NASA api ({}/{} requests remaining): {}", rate_limit.remaining, rate_limit.limit, body),
+            Error::RateLimited { rate_limit: None, body } => write!(f, "rate limited by NASA api: {}", body),
+            Error::Json { path, source, .. } => write!(f, "could not decode response at `{}`: {}", path, source),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Uri(e) => Some(e),
+            Error::Request(e) => Some(e),
+            Error::Http(e) => Some(e),
+            Error::Json { source, .. } => Some(source),
+            Error::Timeout | Error::Status { .. } | Error::RateLimited { .. } => None,
+        }
+    }
+}
+
+impl From<hyper::http::uri::InvalidUri> for Error {
+    fn from(e: hyper::http::uri::InvalidUri) -> Self {
+        Error::Uri(e)
+    }
+}
+
+impl From<hyper::http::Error> for Error {
+    fn from(e: hyper::http::Error) -> Self {
+        Error::Request(e)
+    }
+}
+
+impl From<hyper::Error> for Error {
+    fn from(e: hyper::Error) -> Self {
+        Error::Http(e)
+    }
+}
+
 pub mod models {
     use std::collections::HashMap;
+    use chrono::{NaiveDate, NaiveDateTime};
     use serde::{Serialize,Deserialize};
-    #[derive(Serialize,Deserialize,Debug)]
+    #[derive(Serialize,Deserialize,Debug,Clone)]
     pub struct Links {
+      #[serde(default, skip_serializing_if = "Option::is_none")]
       pub next: Option<String>,
+      #[serde(default, skip_serializing_if = "Option::is_none")]
       pub prev: Option<String>,
       #[serde(rename = "self")]
       pub self_link: Option<String>
     }
-    #[derive(Serialize,Deserialize,Debug)]
+    #[derive(Serialize,Deserialize,Debug,Clone)]
     pub struct EstimatedDiameter {
         pub estimated_diameter_min: f64,
         pub estimated_diameter_max: f64
     }
-    #[derive(Serialize,Deserialize,Debug)]
+    #[derive(Serialize,Deserialize,Debug,Clone)]
     pub struct RelativeVelocity {
-        pub kilometers_per_second: String,
-        pub kilometers_per_hour: String,
-        pub miles_per_hour: String,
+        #[serde(with = "string_number")]
+        pub kilometers_per_second: f64,
+        #[serde(with = "string_number")]
+        pub kilometers_per_hour: f64,
+        #[serde(with = "string_number")]
+        pub miles_per_hour: f64,
     }
-    #[derive(Serialize,Deserialize,Debug)]
+    #[derive(Serialize,Deserialize,Debug,Clone)]
     pub struct MissDistance {
-        pub astronomical: String,
-        pub lunar: String,
-        pub kilometers: String,
-        pub miles: String,
+        #[serde(with = "string_number")]
+        pub astronomical: f64,
+        #[serde(with = "string_number")]
+        pub lunar: f64,
+        #[serde(with = "string_number")]
+        pub kilometers: f64,
+        #[serde(with = "string_number")]
+        pub miles: f64,
     }
-    #[derive(Serialize,Deserialize,Debug)]
+    #[derive(Serialize,Deserialize,Debug,Clone)]
     pub struct CloseApproachEvent {
-        pub close_approach_date: String,
-        pub close_approach_date_full: String,
-        pub epoch_date_close_approach: usize,
+        /// ex. `2022-01-08`
+        #[serde(with = "date_format")]
+        pub close_approach_date: NaiveDate,
+        /// ex. `2022-Jan-08 14:34`, in UTC
+        #[serde(with = "date_time_format")]
+        pub close_approach_date_full: NaiveDateTime,
+        /// Milliseconds since the unix epoch, negative for approaches before 1970
+        pub epoch_date_close_approach: i64,
         pub relative_velocity: RelativeVelocity,
         pub miss_distance: MissDistance,
         pub orbiting_body: String,
     }
-    #[derive(Serialize,Deserialize,Debug)]
+    #[derive(Serialize,Deserialize,Debug,Clone)]
     pub struct EstimatedDiameters {
         pub kilometers: EstimatedDiameter,
         pub meters: EstimatedDiameter,
         pub miles: EstimatedDiameter,
         pub feet: EstimatedDiameter
     }
-    #[derive(Serialize,Deserialize,Debug)]
+    #[derive(Serialize,Deserialize,Debug,Clone)]
     pub struct NearEarthObject {
         pub links: Links,
         pub id: String,
         pub neo_reference_id: String,
+        /// ex. `154347 (2002 XK4)`
+        #[serde(default)]
+        pub name: String,
         pub nasa_jpl_url: String,
         pub absolute_magnitude_h: f64,
         // change string to some enum?
         pub estimated_diameter: EstimatedDiameters,
+        #[serde(default)]
+        pub is_potentially_hazardous_asteroid: bool,
+        #[serde(default)]
         pub close_approach_data: Vec<CloseApproachEvent>,
-        pub is_sentry_object: bool
+        /// only returned by the lookup and browse endpoints, not by the feed
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub orbital_data: Option<OrbitalData>,
+        #[serde(default)]
+        pub is_sentry_object: bool,
+        /// fields we don't model (ex. `name_limited`, `designation`, `sentry_data`), kept so they survive a round trip
+        #[serde(flatten)]
+        pub extra: HashMap<String, serde_json::Value>,
+    }
+    #[derive(Serialize,Deserialize,Debug,Clone,Default)]
+    pub struct OrbitClass {
+        /// ex. `APO`, `ATE`, `AMO`
+        #[serde(default)]
+        pub orbit_class_type: String,
+        #[serde(default)]
+        pub orbit_class_description: String,
+        #[serde(default)]
+        pub orbit_class_range: String,
+    }
+    /// Osculating Keplerian elements, angles are in degrees and distances in AU
+    #[derive(Serialize,Deserialize,Debug,Clone)]
+    pub struct OrbitalData {
+        #[serde(default)]
+        pub orbit_id: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub orbit_determination_date: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub first_observation_date: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub last_observation_date: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub data_arc_in_days: Option<u32>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub observations_used: Option<u32>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub orbit_uncertainty: Option<String>,
+        #[serde(default, with = "option_string_number", skip_serializing_if = "Option::is_none")]
+        pub minimum_orbit_intersection: Option<f64>,
+        #[serde(default, with = "option_string_number", skip_serializing_if = "Option::is_none")]
+        pub jupiter_tisserand_invariant: Option<f64>,
+        /// julian date the elements are valid for
+        #[serde(with = "string_number")]
+        pub epoch_osculation: f64,
+        #[serde(with = "string_number")]
+        pub eccentricity: f64,
+        #[serde(with = "string_number")]
+        pub semi_major_axis: f64,
+        #[serde(with = "string_number")]
+        pub inclination: f64,
+        #[serde(with = "string_number")]
+        pub ascending_node_longitude: f64,
+        /// in days
+        #[serde(default, with = "option_string_number", skip_serializing_if = "Option::is_none")]
+        pub orbital_period: Option<f64>,
+        #[serde(default, with = "option_string_number", skip_serializing_if = "Option::is_none")]
+        pub perihelion_distance: Option<f64>,
+        #[serde(with = "string_number")]
+        pub perihelion_argument: f64,
+        #[serde(default, with = "option_string_number", skip_serializing_if = "Option::is_none")]
+        pub aphelion_distance: Option<f64>,
+        /// julian date
+        #[serde(default, with = "option_string_number", skip_serializing_if = "Option::is_none")]
+        pub perihelion_time: Option<f64>,
+        #[serde(with = "string_number")]
+        pub mean_anomaly: f64,
+        /// in degrees per day
+        #[serde(default, with = "option_string_number", skip_serializing_if = "Option::is_none")]
+        pub mean_motion: Option<f64>,
+        #[serde(default)]
+        pub equinox: String,
+        #[serde(default)]
+        pub orbit_class: OrbitClass,
+        #[serde(flatten)]
+        pub extra: HashMap<String, serde_json::Value>,
+    }
+    #[derive(Serialize,Deserialize,Debug,Clone)]
+    pub struct Page {
+        pub size: usize,
+        pub total_elements: usize,
+        pub total_pages: usize,
+        /// zero based
+        pub number: usize,
+    }
+    #[derive(Serialize,Deserialize,Debug,Clone)]
+    pub struct NearEarthObjectBrowseResponse {
+        pub links: Links,
+        pub page: Page,
+        pub near_earth_objects: Vec<NearEarthObject>
     }
-    #[derive(Serialize,Deserialize,Debug)]
+    #[derive(Serialize,Deserialize,Debug,Clone)]
     pub struct NearEarthObjectResponse {
         pub links: Links,
         pub element_count: usize,
         pub near_earth_objects: HashMap<String, Vec<NearEarthObject>>
     }
+
+    /// NASA encodes most numbers as json strings, read them as either a string or a number
+    /// and write them back out as strings
+    mod string_number {
+        use serde::{Deserialize, Deserializer, Serializer, de::Error};
+
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum StringOrNumber {
+            String(String),
+            Number(f64),
+        }
+
+        pub fn serialize<S: Serializer>(value: &f64, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.collect_str(value)
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+            match StringOrNumber::deserialize(deserializer)? {
+                StringOrNumber::String(value) => value.trim().parse().map_err(D::Error::custom),
+                StringOrNumber::Number(value) => Ok(value),
+            }
+        }
+    }
+
+    mod option_string_number {
+        use serde::{Deserialize, Deserializer, Serializer};
+
+        pub fn serialize<S: Serializer>(value: &Option<f64>, serializer: S) -> Result<S::Ok, S::Error> {
+            match value {
+                Some(value) => super::string_number::serialize(value, serializer),
+                None => serializer.serialize_none(),
+            }
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<f64>, D::Error> {
+            #[derive(Deserialize)]
+            struct Wrapper(#[serde(with = "super::string_number")] f64);
+            Ok(Option::<Wrapper>::deserialize(deserializer)?.map(|Wrapper(value)| value))
+        }
+    }
+
+    mod date_format {
+        use chrono::NaiveDate;
+        use serde::{Deserialize, Deserializer, Serializer, de::Error};
+
+        const FORMAT: &str = "%Y-%m-%d";
+
+        pub fn serialize<S: Serializer>(value: &NaiveDate, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.collect_str(&value.format(FORMAT))
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<NaiveDate, D::Error> {
+            let value = String::deserialize(deserializer)?;
+            NaiveDate::parse_from_str(&value, FORMAT).map_err(D::Error::custom)
+        }
+    }
+
+    mod date_time_format {
+        use chrono::NaiveDateTime;
+        use serde::{Deserialize, Deserializer, Serializer, de::Error};
+
+        const FORMAT: &str = "%Y-%b-%d %H:%M";
+
+        pub fn serialize<S: Serializer>(value: &NaiveDateTime, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.collect_str(&value.format(FORMAT))
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<NaiveDateTime, D::Error> {
+            let value = String::deserialize(deserializer)?;
+            NaiveDateTime::parse_from_str(&value, FORMAT).map_err(D::Error::custom)
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) mod tests {
+        use super::*;
+
+        /// A lookup response for an object that isn't hazardous with a single close approach
+        pub(crate) fn object_json(id: &str) -> String {
+            object_json_with(id, false, &[("2022-01-08", 1641643200000, "14959787.07")])
+        }
+
+        /// A lookup response for an object with close approaches at noon given as date, epoch and miss distance in km
+        pub(crate) fn object_json_with(id: &str, hazardous: bool, approaches: &[(&str, i64, &str)]) -> String {
+            let approaches: Vec<String> = approaches.iter().map(|(close_approach_date, epoch, kilometers)| format!(r#"{{
+                "close_approach_date": "{close_approach_date}",
+                "close_approach_date_full": "{date_full} 12:00",
+                "epoch_date_close_approach": {epoch},
+                "relative_velocity": {{"kilometers_per_second": "10.5", "kilometers_per_hour": "37800", "miles_per_hour": "23487.8"}},
+                "miss_distance": {{"astronomical": "0.1", "lunar": "38.9", "kilometers": "{kilometers}", "miles": "9295806.3"}},
+                "orbiting_body": "Earth"
+            }}"#, close_approach_date = close_approach_date, date_full = date(close_approach_date).format("%Y-%b-%d"), epoch = epoch, kilometers = kilometers)).collect();
+            format!(r#"{{
+                "links": {{"self": "http://api.nasa.gov/neo/rest/v1/neo/{id}"}},
+                "id": "{id}",
+                "neo_reference_id": "{id}",
+                "name": "({id})",
+                "nasa_jpl_url": "http://ssd.jpl.nasa.gov/sbdb.cgi?sstr={id}",
+                "absolute_magnitude_h": 20.1,
+                "estimated_diameter": {{
+                    "kilometers": {{"estimated_diameter_min": 0.2, "estimated_diameter_max": 0.5}},
+                    "meters": {{"estimated_diameter_min": 200, "estimated_diameter_max": 500}},
+                    "miles": {{"estimated_diameter_min": 0.12, "estimated_diameter_max": 0.31}},
+                    "feet": {{"estimated_diameter_min": 656, "estimated_diameter_max": 1640}}
+                }},
+                "is_potentially_hazardous_asteroid": {hazardous},
+                "close_approach_data": [{approaches}],
+                "is_sentry_object": false
+            }}"#, id = id, hazardous = hazardous, approaches = approaches.join(","))
+        }
+
+        pub(crate) fn date(date: &str) -> chrono::NaiveDate {
+            chrono::NaiveDate::parse_from_str(date, "%F").unwrap()
+        }
+
+        #[derive(Serialize, Deserialize, Debug)]
+        struct Numbers {
+            #[serde(with = "string_number")]
+            number: f64,
+            #[serde(default, with = "option_string_number")]
+            optional: Option<f64>,
+        }
+
+        #[test]
+        fn string_numbers_read_strings_and_numbers() {
+            let numbers: Numbers = serde_json::from_str(r#"{"number": " 46677510.425426824 ", "optional": 1.5}"#).unwrap();
+            assert_eq!(numbers.number, 46677510.425426824);
+            assert_eq!(numbers.optional, Some(1.5));
+            let numbers: Numbers = serde_json::from_str(r#"{"number": 2, "optional": null}"#).unwrap();
+            assert_eq!(numbers.number, 2.);
+            assert_eq!(numbers.optional, None);
+            assert!(serde_json::from_str::<Numbers>(r#"{"number": "not a number"}"#).is_err());
+
+            let json = serde_json::to_string(&Numbers { number: 0.25, optional: Some(3.) }).unwrap();
+            assert_eq!(json, r#"{"number":"0.25","optional":"3"}"#);
+        }
+
+        fn event_json_with_date(date_full: &str) -> String {
+            let object: serde_json::Value = serde_json::from_str(&object_json("1")).unwrap();
+            let mut event = object["close_approach_data"][0].clone();
+            event["close_approach_date_full"] = serde_json::Value::from(date_full);
+            event.to_string()
+        }
+
+        #[test]
+        fn close_approach_dates_round_trip() {
+            let object: NearEarthObject = serde_json::from_str(&object_json("2000433")).unwrap();
+            let event = &object.close_approach_data[0];
+            assert_eq!(event.close_approach_date, NaiveDate::from_ymd_opt(2022, 1, 8).unwrap());
+            assert_eq!(event.close_approach_date_full, NaiveDate::from_ymd_opt(2022, 1, 8).unwrap().and_hms_opt(12, 0, 0).unwrap());
+
+            let json = serde_json::to_value(event).unwrap();
+            assert_eq!(json["close_approach_date"], "2022-01-08");
+            assert_eq!(json["close_approach_date_full"], "2022-Jan-08 12:00");
+            assert!(serde_json::from_str::<CloseApproachEvent>(&event_json_with_date("2022-01-08 12:00")).is_err());
+        }
+
+        /// `orbital_data` of 433 Eros as the lookup endpoint sends it
+        const EROS_ORBITAL_DATA: &str = r#"{
+            "orbit_id": "659",
+            "orbit_determination_date": "2021-05-24 17:55:05",
+            "first_observation_date": "1893-10-29",
+            "last_observation_date": "2021-05-13",
+            "data_arc_in_days": 46582,
+            "observations_used": 9130,
+            "orbit_uncertainty": "0",
+            "minimum_orbit_intersection": ".148588",
+            "jupiter_tisserand_invariant": "4.582",
+            "epoch_osculation": "2459600.5",
+            "eccentricity": ".2229512647434284",
+            "semi_major_axis": "1.458045729081037",
+            "inclination": "10.83054121829922",
+            "ascending_node_longitude": "304.2993259000444",
+            "orbital_period": "643.0654021001488",
+            "perihelion_distance": "1.132972589728666",
+            "perihelion_argument": "178.8822959227224",
+            "aphelion_distance": "1.783118868433408",
+            "perihelion_time": "2459787.51207609",
+            "mean_anomaly": "255.5781750109107",
+            "mean_motion": ".5598184685288005",
+            "equinox": "J2000",
+            "orbit_class": {
+                "orbit_class_type": "AMO",
+                "orbit_class_description": "Near-Earth asteroid orbits similar to that of 1221 Amor",
+                "orbit_class_range": "1.017 AU < q (perihelion) < 1.3 AU"
+            }
+        }"#;
+
+        #[test]
+        fn orbital_data_reads_a_lookup_payload() {
+            let orbital_data: OrbitalData = serde_json::from_str(EROS_ORBITAL_DATA).unwrap();
+            assert_eq!(orbital_data.orbit_id, "659");
+            assert_eq!(orbital_data.data_arc_in_days, Some(46582));
+            assert_eq!(orbital_data.observations_used, Some(9130));
+            assert_eq!(orbital_data.minimum_orbit_intersection, Some(0.148588));
+            assert_eq!(orbital_data.eccentricity, 0.2229512647434284);
+            assert_eq!(orbital_data.semi_major_axis, 1.458045729081037);
+            assert_eq!(orbital_data.epoch_osculation, 2459600.5);
+            assert_eq!(orbital_data.mean_motion, Some(0.5598184685288005));
+            assert_eq!(orbital_data.orbit_class.orbit_class_type, "AMO");
+            assert_eq!(orbital_data.orbit_class.orbit_class_range, "1.017 AU < q (perihelion) < 1.3 AU");
+            assert!(orbital_data.extra.is_empty());
+
+            let mut object: serde_json::Value = serde_json::from_str(&object_json("2000433")).unwrap();
+            object["orbital_data"] = serde_json::from_str(EROS_ORBITAL_DATA).unwrap();
+            let object: NearEarthObject = serde_json::from_value(object).unwrap();
+            assert_eq!(object.orbital_data.unwrap().inclination, 10.83054121829922);
+        }
+
+        #[test]
+        fn models_round_trip_unknown_fields() {
+            let mut json: serde_json::Value = serde_json::from_str(&object_json("2000433")).unwrap();
+            json["orbit_quality"] = serde_json::Value::from("excellent");
+            let object: NearEarthObject = serde_json::from_value(json).unwrap();
+            assert_eq!(object.extra["orbit_quality"], "excellent");
+
+            let round_trip: NearEarthObject = serde_json::from_str(&serde_json::to_string(&object).unwrap()).unwrap();
+            assert_eq!(round_trip.id, object.id);
+            assert_eq!(round_trip.estimated_diameter.meters.estimated_diameter_max, 500.);
+            assert_eq!(round_trip.close_approach_data[0].miss_distance.kilometers, 14959787.07);
+            assert_eq!(round_trip.extra["orbit_quality"], "excellent");
+        }
+    }
 }
 
 pub mod client {
-    use hyper::{Client, client::HttpConnector, body};
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+    use std::time::{Duration, Instant};
+
+    use chrono::{NaiveDate, NaiveDateTime, Utc};
+    use diesel::SqliteConnection;
+    use futures::stream::{self, Stream, StreamExt, TryStreamExt};
+    use hyper::{Body, Client, Request, Uri, StatusCode, body, header, HeaderMap};
+    use hyper::client::HttpConnector;
+    use hyper::client::connect::Connect;
     use hyper_tls::HttpsConnector;
+    use rand::Rng;
+    use serde::de::DeserializeOwned;
+
+    use crate::store::NeoStore;
+    use super::Error;
+    use super::models::{Links, NearEarthObject, NearEarthObjectBrowseResponse, NearEarthObjectResponse};
+
+    pub const DEFAULT_BASE_URL: &str = "https://api.nasa.gov";
+    /// Key NASA hands out for exploring the api, heavily rate limited
+    pub const DEMO_API_KEY: &str = "DEMO_KEY";
+    /// The feed endpoint rejects requests where end_date is more than this many days after start_date
+    pub const MAX_FEED_DAYS: i64 = 7;
+    /// NASA rate limits are a rolling hourly window, a quota older than this is no longer accurate
+    const RATE_LIMIT_WINDOW: Duration = Duration::from_secs(60 * 60);
+
+    /// Quota reported by the `X-RateLimit-*` headers of the last response
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct RateLimit {
+        /// requests allowed per hour
+        pub limit: u32,
+        pub remaining: u32,
+    }
+
+    impl RateLimit {
+        fn from_headers(headers: &HeaderMap) -> Option<Self> {
+            let header_value = |name: &str| headers.get(name)?.to_str().ok()?.trim().parse::<u32>().ok();
+            Some(Self {
+                limit: header_value("X-RateLimit-Limit")?,
+                remaining: header_value("X-RateLimit-Remaining")?,
+            })
+        }
+    }
+
+    /// How failed requests are retried, delays grow exponentially from `base_delay` up to `max_delay`
+    /// with random jitter so concurrent requests don't retry in lockstep
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct RetryPolicy {
+        /// total number of attempts, including the first one
+        pub max_attempts: u32,
+        pub base_delay: Duration,
+        pub max_delay: Duration,
+        /// delay before retrying a 429 (when it's listed in `retryable_statuses`), NASA's quota is hourly
+        /// so retrying after `base_delay` would just be refused again
+        pub rate_limited_delay: Duration,
+        /// connection errors and timeouts are always retried, other failures only if their status is listed here
+        pub retryable_statuses: Vec<StatusCode>,
+    }
+
+    impl RetryPolicy {
+        /// Only make a single attempt
+        pub fn none() -> Self {
+            Self {
+                max_attempts: 1,
+                ..Self::default()
+            }
+        }
+
+        fn is_retryable(&self, error: &Error) -> bool {
+            match error {
+                Error::Http(_) | Error::Timeout => true,
+                Error::Status { status, .. } => self.retryable_statuses.contains(status),
+                Error::RateLimited { .. } => self.retryable_statuses.contains(&StatusCode::TOO_MANY_REQUESTS),
+                Error::Uri(_) | Error::Request(_) | Error::Json { .. } => false,
+            }
+        }
+
+        /// Delay before the next attempt, `attempt` is the one that just failed (starting at 1)
+        fn delay(&self, attempt: u32) -> Duration {
+            let exponential = self.base_delay.saturating_mul(2u32.saturating_pow(attempt - 1));
+            let delay = std::cmp::min(exponential, self.max_delay);
+            // jitter between half and the full delay
+            let delay_millis = delay.as_millis() as u64;
+            Duration::from_millis(rand::thread_rng().gen_range(delay_millis / 2..=delay_millis))
+        }
+    }
+
+    impl Default for RetryPolicy {
+        fn default() -> Self {
+            Self {
+                max_attempts: 3,
+                base_delay: Duration::from_millis(500),
+                max_delay: Duration::from_secs(30),
+                rate_limited_delay: Duration::from_secs(10 * 60),
+                retryable_statuses: vec![
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    StatusCode::BAD_GATEWAY,
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    StatusCode::GATEWAY_TIMEOUT,
+                ],
+            }
+        }
+    }
+
+    /// A feed response body previously fetched from NASA
+    #[derive(Debug, Clone)]
+    pub struct CachedResponse {
+        pub response: String,
+        /// `None` if we don't know when it was fetched (ex. rows imported before this was tracked)
+        pub fetched_at: Option<NaiveDateTime>,
+    }
+
+    /// Storage for feed responses keyed by their date window, lets the client skip calling NASA
+    /// for windows it has already fetched. Failures should be logged and treated as a cache miss.
+    pub trait ResponseCache: Send + Sync {
+        fn get(&self, start_date: &str, end_date: &str) -> Option<CachedResponse>;
+        fn put(&self, start_date: &str, end_date: &str, response: &str);
+
+        /// Lookup response of a single object by id, caches that don't keep lookups always miss
+        fn get_object(&self, _id: &str) -> Option<CachedResponse> {
+            None
+        }
+
+        fn put_object(&self, _id: &str, _response: &str) {}
+    }
+
+    /// Cache that only lives as long as the process, keeps lookups as well as feed responses
+    #[derive(Default)]
+    pub struct MemoryResponseCache {
+        responses: Mutex<HashMap<(String, String), CachedResponse>>,
+        objects: Mutex<HashMap<String, CachedResponse>>,
+    }
+
+    impl MemoryResponseCache {
+        pub fn new() -> Self {
+            Self::default()
+        }
+    }
+
+    impl ResponseCache for MemoryResponseCache {
+        fn get(&self, start_date: &str, end_date: &str) -> Option<CachedResponse> {
+            let responses = self.responses.lock().unwrap();
+            responses.get(&(String::from(start_date), String::from(end_date))).cloned()
+        }
+
+        fn put(&self, start_date: &str, end_date: &str, response: &str) {
+            let mut responses = self.responses.lock().unwrap();
+            responses.insert((String::from(start_date), String::from(end_date)), CachedResponse {
+                response: String::from(response),
+                fetched_at: Some(Utc::now().naive_utc()),
+            });
+        }
+
+        fn get_object(&self, id: &str) -> Option<CachedResponse> {
+            self.objects.lock().unwrap().get(id).cloned()
+        }
+
+        fn put_object(&self, id: &str, response: &str) {
+            let mut objects = self.objects.lock().unwrap();
+            objects.insert(String::from(id), CachedResponse {
+                response: String::from(response),
+                fetched_at: Some(Utc::now().naive_utc()),
+            });
+        }
+    }
+
+    /// Cache backed by the `api_response` table, the same table the importer fills.
+    /// Cached responses are projected like imported ones, so they show up in [`NeoStore`] queries.
+    /// There's no table for lookups, they're only kept in memory.
+    pub struct SqliteResponseCache {
+        store: Mutex<NeoStore>,
+        objects: MemoryResponseCache,
+    }
+
+    impl SqliteResponseCache {
+        pub fn new(connection: SqliteConnection) -> Self {
+            Self {
+                store: Mutex::new(NeoStore::new(connection)),
+                objects: MemoryResponseCache::new(),
+            }
+        }
+    }
 
-    use super::models::NearEarthObjectResponse;
+    impl ResponseCache for SqliteResponseCache {
+        fn get(&self, start_date: &str, end_date: &str) -> Option<CachedResponse> {
+            let store = self.store.lock().unwrap();
+            match store.api_response(start_date, end_date) {
+                Ok(record) => record.map(|record| CachedResponse {
+                    response: record.response,
+                    fetched_at: record.fetched_at,
+                }),
+                Err(e) => {
+                    warn!("Could not read cached response for start_date={} end_date={}: {}", start_date, end_date, e);
+                    None
+                },
+            }
+        }
 
-    pub struct NearEarthObjectClient {
+        fn put(&self, start_date: &str, end_date: &str, response: &str) {
+            let store = self.store.lock().unwrap();
+            if let Err(e) = store.upsert_response(start_date, end_date, response) {
+                warn!("Could not cache response for start_date={} end_date={}: {}", start_date, end_date, e);
+            }
+        }
+
+        fn get_object(&self, id: &str) -> Option<CachedResponse> {
+            self.objects.get_object(id)
+        }
+
+        fn put_object(&self, id: &str, response: &str) {
+            self.objects.put_object(id, response)
+        }
+    }
+
+    /// What to do with a request when the last known quota is exhausted
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub enum RateLimitPolicy {
+        /// Fail immediately with [`Error::RateLimited`] without calling NASA
+        #[default]
+        Refuse,
+        /// Sleep for the given duration and then send the request anyways
+        Wait(Duration),
+    }
+
+    /// Whether a feed window was already over when it was fetched, NASA won't add close approaches to it after that.
+    /// With an unknown fetch time (stored before it was tracked) the window is assumed to be fetched after it was over.
+    pub fn is_window_complete(end_date: NaiveDate, fetched_at: Option<NaiveDateTime>) -> bool {
+        match fetched_at {
+            Some(fetched_at) => end_date < fetched_at.date(),
+            None => end_date < Utc::now().naive_utc().date(),
+        }
+    }
+
+    /// Split an inclusive date range into windows the feed endpoint will accept
+    pub fn feed_windows(start_date: NaiveDate, end_date: NaiveDate) -> Vec<(NaiveDate, NaiveDate)> {
+        let mut windows = Vec::new();
+        let mut window_start = start_date;
+        while window_start <= end_date {
+            let window_end = std::cmp::min(window_start + chrono::Duration::days(MAX_FEED_DAYS), end_date);
+            windows.push((window_start, window_end));
+            // add one because the feed is inclusive of the end date
+            window_start = window_end + chrono::Duration::days(1);
+        }
+        windows
+    }
+
+    pub struct NearEarthObjectClient<C = HttpsConnector<HttpConnector>> {
         api_key: String,
-        client: Client<HttpsConnector<HttpConnector>>
+        base_url: String,
+        user_agent: String,
+        timeout: Option<Duration>,
+        concurrency: usize,
+        rate_limit_policy: RateLimitPolicy,
+        retry_policy: RetryPolicy,
+        cache: Option<Box<dyn ResponseCache>>,
+        cache_ttl: Duration,
+        /// latest quota and when it was observed
+        rate_limit: Mutex<Option<(RateLimit, Instant)>>,
+        client: Client<C>
     }
 
     impl NearEarthObjectClient {
+        /// Client for the public NASA api using the default settings
         pub fn new(api_key: &str) -> Self {
-            let https = HttpsConnector::new();
-            let client =  Client::builder().build::<_, hyper::Body>(https);
+            Self::builder().api_key(api_key).build()
+        }
+
+        pub fn builder() -> NearEarthObjectClientBuilder {
+            NearEarthObjectClientBuilder::default()
+        }
+    }
+
+    impl<C> NearEarthObjectClient<C>
+    where
+        C: Connect + Clone + Send + Sync + 'static,
+    {
+        pub async fn get_near_earth_objects(&self, start_date: &str, end_date: &str) -> Result<NearEarthObjectResponse, Error> {
+            let (response, _body) = self.get_near_earth_objects_with_body(start_date, end_date).await?;
+            Ok(response)
+        }
+
+        /// Same as [`Self::get_near_earth_objects`] along with the body exactly as NASA sent it (or as it was cached)
+        pub async fn get_near_earth_objects_with_body(&self, start_date: &str, end_date: &str) -> Result<(NearEarthObjectResponse, String), Error> {
+            if let Some(cached) = self.cached_response(start_date, end_date) {
+                return Ok(cached);
+            }
+            let asteroid_uri = self.uri("/neo/rest/v1/feed", &[("start_date", start_date), ("end_date", end_date)])?;
+            let body_bytes = self.get_body(asteroid_uri).await?;
+            let response = decode(&body_bytes)?;
+            // it decoded, so it's valid utf-8
+            let body = String::from_utf8_lossy(&body_bytes).into_owned();
+            if let Some(cache) = &self.cache {
+                cache.put(start_date, end_date, &body);
+            }
+            Ok((response, body))
+        }
+
+        /// Cached response (and its body) for the window if there is one that is still fresh
+        fn cached_response(&self, start_date: &str, end_date: &str) -> Option<(NearEarthObjectResponse, String)> {
+            let cached = self.cache.as_ref()?.get(start_date, end_date)?;
+            if !self.is_fresh(end_date, cached.fetched_at) {
+                debug!("Cached response for start_date={} end_date={} is stale", start_date, end_date);
+                return None;
+            }
+            match decode(cached.response.as_bytes()) {
+                Ok(response) => {
+                    debug!("Using cached response for start_date={} end_date={}", start_date, end_date);
+                    Some((response, cached.response))
+                },
+                Err(e) => {
+                    warn!("Ignoring cached response for start_date={} end_date={}: {}", start_date, end_date, e);
+                    None
+                }
+            }
+        }
+
+        /// Complete windows (see [`is_window_complete`]) never expire, anything else is only fresh for the cache ttl
+        fn is_fresh(&self, end_date: &str, fetched_at: Option<NaiveDateTime>) -> bool {
+            let complete = NaiveDate::parse_from_str(end_date, "%F")
+                .is_ok_and(|end_date| is_window_complete(end_date, fetched_at));
+            complete || fetched_at.is_some_and(|fetched_at| {
+                chrono::Duration::from_std(self.cache_ttl)
+                    .map(|ttl| Utc::now().naive_utc() - fetched_at < ttl)
+                    .unwrap_or(true)
+            })
+        }
+
+        /// Like [`Self::get_near_earth_objects`] but for any date range, the range is split into
+        /// windows of [`MAX_FEED_DAYS`] which are fetched (up to `concurrency` at a time) and merged
+        pub async fn get_near_earth_objects_range(&self, start_date: NaiveDate, end_date: NaiveDate) -> Result<NearEarthObjectResponse, Error> {
+            let responses: Vec<NearEarthObjectResponse> = stream::iter(feed_windows(start_date, end_date))
+                .map(|(window_start, window_end)| async move {
+                    self.get_near_earth_objects(&window_start.format("%F").to_string(), &window_end.format("%F").to_string()).await
+                })
+                .buffered(self.concurrency)
+                .try_collect()
+                .await?;
+            Ok(merge_responses(responses))
+        }
+
+        /// Look up a single asteroid by its id, the response includes every close approach and the orbital data.
+        /// Cached lookups are used no matter how old they are, orbits and past approaches rarely change.
+        pub async fn get_near_earth_object(&self, id: &str) -> Result<NearEarthObject, Error> {
+            if let Some(cached) = self.cache.as_ref().and_then(|cache| cache.get_object(id)) {
+                match decode(cached.response.as_bytes()) {
+                    Ok(object) => {
+                        debug!("Using cached lookup for id={}", id);
+                        return Ok(object);
+                    },
+                    Err(e) => warn!("Ignoring cached lookup for id={}: {}", id, e),
+                }
+            }
+            let asteroid_uri = self.uri(&format!("/neo/rest/v1/neo/{}", id), &[])?;
+            let body_bytes = self.get_body(asteroid_uri).await?;
+            let object = decode(&body_bytes)?;
+            if let Some(cache) = &self.cache {
+                cache.put_object(id, &String::from_utf8_lossy(&body_bytes));
+            }
+            Ok(object)
+        }
+
+        /// Look up each object (the feed leaves out orbital data), up to `concurrency` at a time.
+        /// Results are yielded as each lookup finishes, so callers can use them before the rest are done.
+        pub fn get_near_earth_objects_by_id(&self, ids: Vec<String>) -> impl Stream<Item = (String, Result<NearEarthObject, Error>)> + '_ {
+            stream::iter(ids)
+                .map(move |id| async move {
+                    let object = self.get_near_earth_object(&id).await;
+                    (id, object)
+                })
+                .buffer_unordered(self.concurrency)
+        }
+
+        /// Fetch a single page of the whole asteroid catalogue, pages are zero based
+        pub async fn browse_page(&self, page: usize) -> Result<NearEarthObjectBrowseResponse, Error> {
+            let asteroid_uri = self.uri("/neo/rest/v1/neo/browse", &[("page", &page.to_string())])?;
+            self.get(asteroid_uri).await
+        }
+
+        /// Stream every asteroid in the catalogue starting at `start_page`.
+        ///
+        /// Pages are only requested as the stream is polled, and no more than `max_pages` are fetched.
+        /// The stream ends after yielding the first error.
+        pub fn browse(&self, start_page: usize, max_pages: Option<usize>) -> impl Stream<Item = Result<NearEarthObject, Error>> + '_ {
+            let end_page = max_pages.map(|max_pages| start_page + max_pages);
+            stream::unfold(Some(start_page), move |page| async move {
+                let page = page?;
+                if end_page.is_some_and(|end_page| page >= end_page) {
+                    return None;
+                }
+                debug!("Browsing near earth objects page={}", page);
+                match self.browse_page(page).await {
+                    Ok(response) => {
+                        let has_next = response.links.next.is_some() && response.page.number + 1 < response.page.total_pages;
+                        let objects: Vec<_> = response.near_earth_objects.into_iter().map(Ok).collect();
+                        Some((stream::iter(objects), if has_next { Some(page + 1) } else { None }))
+                    },
+                    Err(e) => Some((stream::iter(vec![Err(e)]), None)),
+                }
+            }).flatten()
+        }
+
+        /// Quota reported by the most recent NASA response, `None` if no request has been made yet
+        /// or the quota is older than NASA's rolling window
+        pub fn rate_limit(&self) -> Option<RateLimit> {
+            let rate_limit = self.rate_limit.lock().unwrap();
+            rate_limit
+                .filter(|(_, observed_at)| observed_at.elapsed() < RATE_LIMIT_WINDOW)
+                .map(|(rate_limit, _)| rate_limit)
+        }
+
+        fn set_rate_limit(&self, rate_limit: Option<RateLimit>) {
+            *self.rate_limit.lock().unwrap() = rate_limit.map(|rate_limit| (rate_limit, Instant::now()));
+        }
+
+        /// Refuse or delay the request if the last response said there is no quota left
+        async fn check_rate_limit(&self) -> Result<(), Error> {
+            match self.rate_limit() {
+                Some(rate_limit) if rate_limit.remaining == 0 => match self.rate_limit_policy {
+                    RateLimitPolicy::Refuse => Err(Error::RateLimited {
+                        rate_limit: Some(rate_limit),
+                        body: String::from("no requests remaining, refusing to call NASA api"),
+                    }),
+                    RateLimitPolicy::Wait(delay) => {
+                        warn!("NASA api quota exhausted, waiting {:?} before sending request", delay);
+                        tokio::time::sleep(delay).await;
+                        // the quota is unknown until the next response comes back
+                        self.set_rate_limit(None);
+                        Ok(())
+                    },
+                },
+                _ => Ok(()),
+            }
+        }
+
+        /// Builds the uri for an api path, the api key is appended to the query parameters
+        fn uri(&self, path: &str, params: &[(&str, &str)]) -> Result<Uri, Error> {
+            let mut uri = format!("{}{}?", self.base_url, path);
+            for (key, value) in params {
+                uri.push_str(&format!("{}={}&", key, value));
+            }
+            uri.push_str(&format!("api_key={}", self.api_key));
+            Ok(uri.parse()?)
+        }
+
+        /// Makes a GET request and decodes the json body into `T`
+        async fn get<T: DeserializeOwned>(&self, uri: Uri) -> Result<T, Error> {
+            let body_bytes = self.get_body(uri).await?;
+            decode(&body_bytes)
+        }
+
+        /// Makes a GET request for the raw body, retrying according to the retry policy
+        async fn get_body(&self, uri: Uri) -> Result<body::Bytes, Error> {
+            let max_attempts = std::cmp::max(self.retry_policy.max_attempts, 1);
+            let mut attempt = 1;
+            loop {
+                // don't log the whole uri, it contains the api key
+                debug!("GET {} attempt {}/{}", uri.path(), attempt, max_attempts);
+                match self.get_body_once(uri.clone()).await {
+                    Err(e) if attempt < max_attempts && self.retry_policy.is_retryable(&e) => {
+                        let rate_limited = matches!(e, Error::RateLimited { .. });
+                        let delay = if rate_limited { self.retry_policy.rate_limited_delay } else { self.retry_policy.delay(attempt) };
+                        warn!("GET {} attempt {}/{} failed: {}, retrying in {:?}", uri.path(), attempt, max_attempts, e, delay);
+                        tokio::time::sleep(delay).await;
+                        if rate_limited {
+                            // already waited for the quota, don't wait again for the exhausted quota the 429 left behind
+                            self.set_rate_limit(None);
+                        }
+                        attempt += 1;
+                    },
+                    result => return result,
+                }
+            }
+        }
+
+        async fn get_body_once(&self, uri: Uri) -> Result<body::Bytes, Error> {
+            self.check_rate_limit().await?;
+            let request = Request::get(uri)
+                .header(header::USER_AGENT, &self.user_agent)
+                .body(Body::empty())?;
+            let (status, body_bytes) = match self.timeout {
+                Some(timeout) => tokio::time::timeout(timeout, self.send(request)).await.map_err(|_| Error::Timeout)??,
+                None => self.send(request).await?,
+            };
+            if status == StatusCode::TOO_MANY_REQUESTS {
+                let rate_limit = self.rate_limit().map(|rate_limit| RateLimit { remaining: 0, ..rate_limit });
+                self.set_rate_limit(rate_limit);
+                return Err(Error::RateLimited { rate_limit, body: String::from_utf8_lossy(&body_bytes).into_owned() });
+            }
+            if !status.is_success() {
+                return Err(Error::Status { status, body: String::from_utf8_lossy(&body_bytes).into_owned() });
+            }
+            Ok(body_bytes)
+        }
+
+        async fn send(&self, request: Request<Body>) -> Result<(StatusCode, body::Bytes), Error> {
+            let resp = self.client.request(request).await?;
+            let status = resp.status();
+            if let Some(rate_limit) = RateLimit::from_headers(resp.headers()) {
+                trace!("rate_limit: {:?}", rate_limit);
+                self.set_rate_limit(Some(rate_limit));
+            }
+            let body_bytes = body::to_bytes(resp.into_body()).await?;
+            Ok((status, body_bytes))
+        }
+    }
+
+    /// Builder for a [`NearEarthObjectClient`], useful for pointing the client at a mock server or proxy
+    pub struct NearEarthObjectClientBuilder<C = HttpsConnector<HttpConnector>> {
+        api_key: String,
+        base_url: String,
+        user_agent: String,
+        timeout: Option<Duration>,
+        concurrency: usize,
+        rate_limit_policy: RateLimitPolicy,
+        retry_policy: RetryPolicy,
+        cache: Option<Box<dyn ResponseCache>>,
+        cache_ttl: Duration,
+        connector: C,
+    }
+
+    impl Default for NearEarthObjectClientBuilder {
+        fn default() -> Self {
             Self {
-                api_key: String::from(api_key),
+                api_key: String::from(DEMO_API_KEY),
+                base_url: String::from(DEFAULT_BASE_URL),
+                user_agent: format!("rocks/{}", env!("CARGO_PKG_VERSION")),
+                timeout: None,
+                concurrency: 1,
+                rate_limit_policy: RateLimitPolicy::default(),
+                retry_policy: RetryPolicy::default(),
+                cache: None,
+                cache_ttl: Duration::from_secs(60 * 60),
+                connector: HttpsConnector::new(),
+            }
+        }
+    }
+
+    impl<C> NearEarthObjectClientBuilder<C> {
+        pub fn api_key(mut self, api_key: &str) -> Self {
+            self.api_key = String::from(api_key);
+            self
+        }
+
+        /// Scheme and authority the api paths are appended to, ex. `http://localhost:8080`
+        pub fn base_url(mut self, base_url: &str) -> Self {
+            self.base_url = String::from(base_url.trim_end_matches('/'));
+            self
+        }
+
+        pub fn user_agent(mut self, user_agent: &str) -> Self {
+            self.user_agent = String::from(user_agent);
+            self
+        }
+
+        /// Maximum time to wait for a response, including reading the body
+        pub fn timeout(mut self, timeout: Duration) -> Self {
+            self.timeout = Some(timeout);
+            self
+        }
+
+        /// Maximum number of requests in flight when fetching a date range, defaults to 1
+        pub fn concurrency(mut self, concurrency: usize) -> Self {
+            self.concurrency = std::cmp::max(concurrency, 1);
+            self
+        }
+
+        /// What to do when the quota is used up, defaults to [`RateLimitPolicy::Refuse`]
+        pub fn rate_limit_policy(mut self, rate_limit_policy: RateLimitPolicy) -> Self {
+            self.rate_limit_policy = rate_limit_policy;
+            self
+        }
+
+        /// How connection errors, timeouts and 5xx responses are retried, see [`RetryPolicy::default`]
+        pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+            self.retry_policy = retry_policy;
+            self
+        }
+
+        /// Serve feed requests for windows that were already fetched from the cache
+        pub fn cache<R: ResponseCache + 'static>(mut self, cache: R) -> Self {
+            self.cache = Some(Box::new(cache));
+            self
+        }
+
+        /// How long cached windows that include today (or the future) are used before fetching again, defaults to an hour
+        pub fn cache_ttl(mut self, cache_ttl: Duration) -> Self {
+            self.cache_ttl = cache_ttl;
+            self
+        }
+
+        /// Replace the default https connector
+        pub fn connector<D>(self, connector: D) -> NearEarthObjectClientBuilder<D> {
+            NearEarthObjectClientBuilder {
+                api_key: self.api_key,
+                base_url: self.base_url,
+                user_agent: self.user_agent,
+                timeout: self.timeout,
+                concurrency: self.concurrency,
+                rate_limit_policy: self.rate_limit_policy,
+                retry_policy: self.retry_policy,
+                cache: self.cache,
+                cache_ttl: self.cache_ttl,
+                connector,
+            }
+        }
+
+        pub fn build(self) -> NearEarthObjectClient<C>
+        where
+            C: Connect + Clone + Send + Sync + 'static,
+        {
+            let client = Client::builder().build::<_, Body>(self.connector);
+            NearEarthObjectClient {
+                api_key: self.api_key,
+                base_url: self.base_url,
+                user_agent: self.user_agent,
+                timeout: self.timeout,
+                concurrency: self.concurrency,
+                rate_limit_policy: self.rate_limit_policy,
+                retry_policy: self.retry_policy,
+                cache: self.cache,
+                cache_ttl: self.cache_ttl,
+                rate_limit: Mutex::new(None),
                 client
             }
         }
+    }
+
+    /// Combine feed responses (ordered by date) into a single response
+    fn merge_responses(responses: Vec<NearEarthObjectResponse>) -> NearEarthObjectResponse {
+        let links = Links {
+            prev: responses.first().and_then(|response| response.links.prev.clone()),
+            next: responses.last().and_then(|response| response.links.next.clone()),
+            self_link: None,
+        };
+        let mut near_earth_objects: HashMap<String, Vec<NearEarthObject>> = HashMap::new();
+        for response in responses {
+            for (date, objects) in response.near_earth_objects {
+                near_earth_objects.entry(date).or_default().extend(objects);
+            }
+        }
+        NearEarthObjectResponse {
+            links,
+            element_count: near_earth_objects.values().map(Vec::len).sum(),
+            near_earth_objects,
+        }
+    }
+
+    /// Deserialize json, keeping track of the path to the field that failed
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, Error> {
+        let deserializer = &mut serde_json::Deserializer::from_slice(bytes);
+        serde_path_to_error::deserialize(deserializer).map_err(|e| Error::Json {
+            path: e.path().to_string(),
+            source: e.into_inner(),
+            body: String::from_utf8_lossy(bytes).into_owned(),
+        })
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        use std::collections::VecDeque;
+        use std::convert::Infallible;
+        use std::sync::{Arc, Mutex};
+
+        use hyper::{Response, Server};
+        use hyper::service::{make_service_fn, service_fn};
+
+        use super::super::models::tests::{date, object_json, object_json_with};
+
+        /// Status, headers and body the mock server answers a request with
+        type MockResponse = (StatusCode, Vec<(&'static str, &'static str)>, String);
+
+        /// Requests the mock server received, as the path with query and the user agent
+        type ReceivedRequests = Arc<Mutex<Vec<(String, String)>>>;
+
+        /// Serve the responses in order from a local port, returns the base url and the requests received
+        fn mock_server(responses: Vec<MockResponse>) -> (String, ReceivedRequests) {
+            let responses = Arc::new(Mutex::new(VecDeque::from(responses)));
+            let requests: ReceivedRequests = Arc::new(Mutex::new(Vec::new()));
+            let received = Arc::clone(&requests);
+            let make_service = make_service_fn(move |_| {
+                let responses = Arc::clone(&responses);
+                let requests = Arc::clone(&requests);
+                async move {
+                    Ok::<_, Infallible>(service_fn(move |request: Request<Body>| {
+                        let user_agent = request.headers().get(header::USER_AGENT)
+                            .and_then(|user_agent| user_agent.to_str().ok())
+                            .unwrap_or_default();
+                        requests.lock().unwrap().push((request.uri().to_string(), String::from(user_agent)));
+                        let (status, headers, body) = responses.lock().unwrap().pop_front().expect("unexpected request");
+                        let mut response = Response::builder().status(status);
+                        for (name, value) in headers {
+                            response = response.header(name, value);
+                        }
+                        let response = response.body(Body::from(body)).unwrap();
+                        async move { Ok::<_, Infallible>(response) }
+                    }))
+                }
+            });
+            let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+            let base_url = format!("http://{}", server.local_addr());
+            tokio::spawn(server);
+            (base_url, received)
+        }
+
+        fn test_client(base_url: &str) -> NearEarthObjectClient<HttpConnector> {
+            NearEarthObjectClient::builder()
+                .connector(HttpConnector::new())
+                .base_url(base_url)
+                .api_key("test-key")
+                .user_agent("rocks-test")
+                .build()
+        }
+
+        fn feed_json(date: &str, ids: &[&str]) -> String {
+            let objects: Vec<String> = ids.iter().map(|id| object_json(id)).collect();
+            format!(r#"{{
+                "links": {{"self": "http://api.nasa.gov/neo/rest/v1/feed?start_date={date}&end_date={date}"}},
+                "element_count": {count},
+                "near_earth_objects": {{"{date}": [{objects}]}}
+            }}"#, date = date, count = ids.len(), objects = objects.join(","))
+        }
+
+        fn browse_json(number: usize, total_pages: usize, ids: &[&str], next: bool) -> String {
+            let objects: Vec<String> = ids.iter().map(|id| object_json(id)).collect();
+            let next = if next { format!(r#""next": "http://api.nasa.gov/neo/rest/v1/neo/browse?page={}","#, number + 1) } else { String::new() };
+            format!(r#"{{
+                "links": {{{next} "self": "http://api.nasa.gov/neo/rest/v1/neo/browse?page={number}"}},
+                "page": {{"size": {size}, "total_elements": {total}, "total_pages": {total_pages}, "number": {number}}},
+                "near_earth_objects": [{objects}]
+            }}"#, next = next, number = number, size = ids.len(), total = ids.len() * total_pages, total_pages = total_pages, objects = objects.join(","))
+        }
+
+        fn browsed_pages(requests: &ReceivedRequests) -> Vec<String> {
+            requests.lock().unwrap().iter().map(|(uri, _)| uri.clone()).collect()
+        }
+
+        #[tokio::test]
+        async fn browse_follows_next_links() {
+            let (base_url, requests) = mock_server(vec![
+                (StatusCode::OK, vec![], browse_json(0, 3, &["1", "2"], true)),
+                (StatusCode::OK, vec![], browse_json(1, 3, &["3", "4"], true)),
+                (StatusCode::OK, vec![], browse_json(2, 3, &["5"], false)),
+            ]);
+            let client = test_client(&base_url);
+
+            let ids: Vec<String> = client.browse(0, None).map(|object| object.unwrap().id).collect().await;
+            assert_eq!(ids, vec!["1", "2", "3", "4", "5"]);
+            assert_eq!(browsed_pages(&requests), vec![
+                "/neo/rest/v1/neo/browse?page=0&api_key=test-key",
+                "/neo/rest/v1/neo/browse?page=1&api_key=test-key",
+                "/neo/rest/v1/neo/browse?page=2&api_key=test-key",
+            ]);
+        }
+
+        #[tokio::test]
+        async fn browse_stops_at_the_last_page() {
+            // the last page still links to a next page that doesn't exist
+            let (base_url, requests) = mock_server(vec![(StatusCode::OK, vec![], browse_json(0, 1, &["1"], true))]);
+            let client = test_client(&base_url);
+
+            let ids: Vec<String> = client.browse(0, None).map(|object| object.unwrap().id).collect().await;
+            assert_eq!(ids, vec!["1"]);
+            assert_eq!(requests.lock().unwrap().len(), 1);
+        }
+
+        #[tokio::test]
+        async fn browse_starts_at_start_page_and_fetches_max_pages() {
+            let (base_url, requests) = mock_server(vec![
+                (StatusCode::OK, vec![], browse_json(2, 10, &["1"], true)),
+                (StatusCode::OK, vec![], browse_json(3, 10, &["2"], true)),
+            ]);
+            let client = test_client(&base_url);
+
+            let ids: Vec<String> = client.browse(2, Some(2)).map(|object| object.unwrap().id).collect().await;
+            assert_eq!(ids, vec!["1", "2"]);
+            assert_eq!(browsed_pages(&requests), vec![
+                "/neo/rest/v1/neo/browse?page=2&api_key=test-key",
+                "/neo/rest/v1/neo/browse?page=3&api_key=test-key",
+            ]);
+            assert_eq!(client.browse(0, Some(0)).count().await, 0);
+        }
+
+        #[tokio::test]
+        async fn browse_ends_after_the_first_error() {
+            let (base_url, requests) = mock_server(vec![
+                (StatusCode::OK, vec![], browse_json(0, 3, &["1"], true)),
+                (StatusCode::NOT_FOUND, vec![], String::from("not found")),
+            ]);
+            let client = test_client(&base_url);
+
+            let objects: Vec<Result<NearEarthObject, Error>> = client.browse(0, None).collect().await;
+            assert_eq!(objects.len(), 2);
+            assert_eq!(objects[0].as_ref().unwrap().id, "1");
+            assert!(matches!(objects[1], Err(Error::Status { status: StatusCode::NOT_FOUND, .. })));
+            assert_eq!(requests.lock().unwrap().len(), 2);
+        }
+
+        #[tokio::test]
+        async fn builder_sends_requests_to_base_url() {
+            let (base_url, requests) = mock_server(vec![(StatusCode::OK, vec![], feed_json("2022-01-08", &["2000433"]))]);
+            let client = test_client(&format!("{}/", base_url));
+
+            let response = client.get_near_earth_objects("2022-01-08", "2022-01-08").await.unwrap();
+            assert_eq!(response.near_earth_objects["2022-01-08"][0].id, "2000433");
+            let requests = requests.lock().unwrap();
+            assert_eq!(*requests, vec![(
+                String::from("/neo/rest/v1/feed?start_date=2022-01-08&end_date=2022-01-08&api_key=test-key"),
+                String::from("rocks-test"),
+            )]);
+        }
+
+        #[test]
+        fn rate_limit_from_headers() {
+            let mut headers = HeaderMap::new();
+            assert_eq!(RateLimit::from_headers(&headers), None);
+            headers.insert("X-RateLimit-Limit", " 1000".parse().unwrap());
+            assert_eq!(RateLimit::from_headers(&headers), None);
+            headers.insert("X-RateLimit-Remaining", "998".parse().unwrap());
+            assert_eq!(RateLimit::from_headers(&headers), Some(RateLimit { limit: 1000, remaining: 998 }));
+            headers.insert("X-RateLimit-Remaining", "lots".parse().unwrap());
+            assert_eq!(RateLimit::from_headers(&headers), None);
+        }
+
+        #[tokio::test]
+        async fn too_many_requests_is_rate_limited() {
+            let rate_limit_headers = vec![("X-RateLimit-Limit", "30"), ("X-RateLimit-Remaining", "1")];
+            let (base_url, requests) = mock_server(vec![
+                (StatusCode::OK, rate_limit_headers.clone(), object_json("1")),
+                (StatusCode::TOO_MANY_REQUESTS, vec![], String::from(r#"{"error": {"code": "OVER_RATE_LIMIT"}}"#)),
+            ]);
+            let client = test_client(&base_url);
+
+            client.get_near_earth_object("1").await.unwrap();
+            assert_eq!(client.rate_limit(), Some(RateLimit { limit: 30, remaining: 1 }));
+            match client.get_near_earth_object("2").await {
+                Err(Error::RateLimited { rate_limit, body }) => {
+                    assert_eq!(rate_limit, Some(RateLimit { limit: 30, remaining: 0 }));
+                    assert!(body.contains("OVER_RATE_LIMIT"), "{}", body);
+                },
+                result => panic!("expected rate limited error, got {:?}", result.map(|object| object.id)),
+            }
+            // the default policy refuses without calling NASA once the quota is used up
+            assert!(matches!(client.get_near_earth_object("3").await, Err(Error::RateLimited { .. })));
+            assert_eq!(requests.lock().unwrap().len(), 2);
+        }
+
+        #[test]
+        fn retry_delay_grows_up_to_max_delay() {
+            let retry_policy = RetryPolicy {
+                base_delay: Duration::from_millis(100),
+                max_delay: Duration::from_millis(1000),
+                ..RetryPolicy::default()
+            };
+            for (attempt, full_delay) in [(1, 100), (2, 200), (3, 400), (4, 800), (5, 1000), (40, 1000)] {
+                let delay = retry_policy.delay(attempt).as_millis() as u64;
+                assert!(delay >= full_delay / 2 && delay <= full_delay, "attempt {} delay {}ms", attempt, delay);
+            }
+        }
+
+        #[test]
+        fn retryable_errors() {
+            let retry_policy = RetryPolicy::default();
+            let status = |status: StatusCode| Error::Status { status, body: String::new() };
+            assert!(retry_policy.is_retryable(&Error::Timeout));
+            assert!(retry_policy.is_retryable(&status(StatusCode::SERVICE_UNAVAILABLE)));
+            assert!(!retry_policy.is_retryable(&status(StatusCode::FORBIDDEN)));
+            assert!(!retry_policy.is_retryable(&Error::RateLimited { rate_limit: None, body: String::new() }));
+            let json_error = serde_json::from_str::<u32>("{").unwrap_err();
+            assert!(!retry_policy.is_retryable(&Error::Json { path: String::new(), source: json_error, body: String::new() }));
+
+            let retry_policy = RetryPolicy { retryable_statuses: vec![StatusCode::TOO_MANY_REQUESTS], ..RetryPolicy::default() };
+            assert!(retry_policy.is_retryable(&Error::RateLimited { rate_limit: None, body: String::new() }));
+        }
+
+        #[tokio::test]
+        async fn server_errors_are_retried() {
+            let (base_url, requests) = mock_server(vec![
+                (StatusCode::SERVICE_UNAVAILABLE, vec![], String::from("try again")),
+                (StatusCode::BAD_GATEWAY, vec![], String::from("try again")),
+                (StatusCode::OK, vec![], object_json("1")),
+                (StatusCode::SERVICE_UNAVAILABLE, vec![], String::from("try again")),
+                (StatusCode::TOO_MANY_REQUESTS, vec![], String::from("slow down")),
+            ]);
+            let client = NearEarthObjectClient::builder()
+                .connector(HttpConnector::new())
+                .base_url(&base_url)
+                .retry_policy(RetryPolicy {
+                    base_delay: Duration::from_millis(1),
+                    max_delay: Duration::from_millis(1),
+                    ..RetryPolicy::default()
+                })
+                .build();
+
+            assert_eq!(client.get_near_earth_object("1").await.unwrap().id, "1");
+            assert_eq!(requests.lock().unwrap().len(), 3);
+            // 429 isn't retried by default, so the second lookup gives up after two attempts
+            assert!(matches!(client.get_near_earth_object("2").await, Err(Error::RateLimited { .. })));
+            assert_eq!(requests.lock().unwrap().len(), 5);
+        }
+
+        #[tokio::test]
+        async fn too_many_requests_are_retried_when_listed() {
+            let (base_url, requests) = mock_server(vec![
+                (StatusCode::TOO_MANY_REQUESTS, vec![("X-RateLimit-Limit", "1000"), ("X-RateLimit-Remaining", "0")], String::from("slow down")),
+                (StatusCode::OK, vec![("X-RateLimit-Limit", "1000"), ("X-RateLimit-Remaining", "999")], object_json("1")),
+            ]);
+            let client = NearEarthObjectClient::builder()
+                .connector(HttpConnector::new())
+                .base_url(&base_url)
+                // waiting for the quota would take the whole delay again if the 429's quota was kept
+                .rate_limit_policy(RateLimitPolicy::Wait(Duration::from_secs(60 * 60)))
+                .retry_policy(RetryPolicy {
+                    rate_limited_delay: Duration::from_millis(1),
+                    retryable_statuses: vec![StatusCode::TOO_MANY_REQUESTS],
+                    ..RetryPolicy::default()
+                })
+                .build();
+
+            assert_eq!(client.get_near_earth_object("1").await.unwrap().id, "1");
+            assert_eq!(requests.lock().unwrap().len(), 2);
+            assert_eq!(client.rate_limit().unwrap().remaining, 999);
+        }
+
+        #[test]
+        fn cached_windows_expire_unless_they_were_over() {
+            let client = NearEarthObjectClient::builder().cache_ttl(Duration::from_secs(60 * 60)).build();
+            let now = Utc::now().naive_utc();
+            let today = now.date().format("%F").to_string();
+            let hours_ago = |hours: i64| Some(now - chrono::Duration::hours(hours));
+
+            // fetched after the window was over
+            assert!(client.is_fresh("2022-01-08", Some(date("2022-01-09").and_hms_opt(0, 0, 0).unwrap())));
+            // fetched while the window was still going
+            assert!(!client.is_fresh("2022-01-08", Some(date("2022-01-08").and_hms_opt(23, 0, 0).unwrap())));
+            assert!(client.is_fresh(&today, hours_ago(0)));
+            assert!(!client.is_fresh(&today, hours_ago(2)));
+            // unknown fetch time
+            assert!(client.is_fresh("2022-01-08", None));
+            assert!(!client.is_fresh(&today, None));
+            assert!(!client.is_fresh("not a date", None));
+            assert!(client.is_fresh("not a date", hours_ago(0)));
+        }
+
+        #[tokio::test]
+        async fn cached_windows_are_not_fetched_again() {
+            let (base_url, requests) = mock_server(vec![(StatusCode::OK, vec![], feed_json("2022-01-08", &["1"]))]);
+            let client = NearEarthObjectClient::builder()
+                .connector(HttpConnector::new())
+                .base_url(&base_url)
+                .cache(MemoryResponseCache::new())
+                .build();
+
+            for _ in 0..2 {
+                let response = client.get_near_earth_objects("2022-01-08", "2022-01-08").await.unwrap();
+                assert_eq!(response.element_count, 1);
+            }
+            assert_eq!(requests.lock().unwrap().len(), 1);
+        }
+
+        #[tokio::test]
+        async fn lookup_reads_approaches_before_1970() {
+            // 433 Eros has been observed since the 1890s
+            let object = object_json_with("2000433", false, &[
+                ("1900-12-27", -2177841600000, "47112732.928149391"),
+                ("2022-01-08", 1641643200000, "14959787.07"),
+            ]);
+            let (base_url, _) = mock_server(vec![(StatusCode::OK, vec![], object)]);
+
+            let object = test_client(&base_url).get_near_earth_object("2000433").await.unwrap();
+            let early_approach = &object.close_approach_data[0];
+            assert_eq!(early_approach.epoch_date_close_approach, -2177841600000);
+            assert_eq!(early_approach.close_approach_date, date("1900-12-27"));
+        }
+
+        #[tokio::test]
+        async fn lookups_are_cached_by_id() {
+            let (base_url, requests) = mock_server(vec![
+                (StatusCode::OK, vec![], object_json("1")),
+                (StatusCode::OK, vec![], object_json("2")),
+            ]);
+            let client = NearEarthObjectClient::builder()
+                .connector(HttpConnector::new())
+                .base_url(&base_url)
+                .cache(MemoryResponseCache::new())
+                .build();
+
+            let ids = vec![String::from("1"), String::from("2")];
+            for _ in 0..2 {
+                let mut objects: Vec<String> = client.get_near_earth_objects_by_id(ids.clone())
+                    .map(|(id, object)| {
+                        assert_eq!(object.unwrap().id, id);
+                        id
+                    })
+                    .collect()
+                    .await;
+                objects.sort();
+                assert_eq!(objects, ids);
+            }
+            assert_eq!(requests.lock().unwrap().len(), 2);
+        }
+
+        #[test]
+        fn sqlite_cache_projects_responses() {
+            let connection = crate::db_util::try_establish_connection(":memory:", &crate::db_util::ConnectionOptions::default()).unwrap();
+            let cache = SqliteResponseCache::new(connection);
+            let body = feed_json("2022-01-08", &["1", "2"]);
+            cache.put("2022-01-08", "2022-01-08", &body);
+
+            let cached = cache.get("2022-01-08", "2022-01-08").unwrap();
+            assert_eq!(cached.response, body);
+            assert!(cached.fetched_at.is_some());
+            let store = cache.store.lock().unwrap();
+            assert_eq!(store.objects_between(date("2022-01-08"), date("2022-01-08")).unwrap().len(), 2);
+        }
+
+        #[test]
+        fn feed_windows_split_inclusive_ranges() {
+            assert_eq!(feed_windows(date("2022-01-01"), date("2022-01-01")), vec![(date("2022-01-01"), date("2022-01-01"))]);
+            assert_eq!(feed_windows(date("2022-01-01"), date("2022-01-08")), vec![(date("2022-01-01"), date("2022-01-08"))]);
+            assert_eq!(feed_windows(date("2022-01-01"), date("2022-01-20")), vec![
+                (date("2022-01-01"), date("2022-01-08")),
+                (date("2022-01-09"), date("2022-01-16")),
+                (date("2022-01-17"), date("2022-01-20")),
+            ]);
+            assert!(feed_windows(date("2022-01-02"), date("2022-01-01")).is_empty());
+        }
+
+        #[test]
+        fn merge_responses_combines_dates() {
+            let decode_feed = |json: String| serde_json::from_str::<NearEarthObjectResponse>(&json).unwrap();
+            let mut first = decode_feed(feed_json("2022-01-08", &["1", "2"]));
+            first.links.prev = Some(String::from("prev"));
+            let mut second = decode_feed(feed_json("2022-01-09", &["3"]));
+            second.links.next = Some(String::from("next"));
+
+            let merged = merge_responses(vec![first, second]);
+            assert_eq!(merged.element_count, 3);
+            assert_eq!(merged.near_earth_objects["2022-01-08"].len(), 2);
+            assert_eq!(merged.near_earth_objects["2022-01-09"][0].id, "3");
+            assert_eq!(merged.links.prev.as_deref(), Some("prev"));
+            assert_eq!(merged.links.next.as_deref(), Some("next"));
+            assert!(merged.links.self_link.is_none());
+        }
+
+        #[tokio::test]
+        async fn range_is_fetched_in_windows() {
+            let (base_url, requests) = mock_server(vec![
+                (StatusCode::OK, vec![], feed_json("2022-01-01", &["1"])),
+                (StatusCode::OK, vec![], feed_json("2022-01-09", &["2", "3"])),
+            ]);
+            let client = test_client(&base_url);
 
-        pub async fn get_near_earth_objects(&self, start_date: &str, end_date: &str) -> Result<NearEarthObjectResponse, Box<dyn std::error::Error + Send + Sync>> {
-            let asteroid_uri = format!("https://api.nasa.gov/neo/rest/v1/feed?start_date={}&end_date={}&api_key={}", start_date, end_date, self.api_key).parse()?;
-            let mut resp = self.client.get(asteroid_uri).await?;
-            let body = resp.body_mut();
-            let body_bytes = body::to_bytes(body).await?;
-            let asteroid_data: NearEarthObjectResponse = serde_json::from_slice(&body_bytes).unwrap();
-            Ok(asteroid_data)
+            let response = client.get_near_earth_objects_range(date("2022-01-01"), date("2022-01-10")).await.unwrap();
+            assert_eq!(response.element_count, 3);
+            let uris: Vec<String> = requests.lock().unwrap().iter().map(|(uri, _)| uri.clone()).collect();
+            assert_eq!(uris, vec![
+                "/neo/rest/v1/feed?start_date=2022-01-01&end_date=2022-01-08&api_key=test-key",
+                "/neo/rest/v1/feed?start_date=2022-01-09&end_date=2022-01-10&api_key=test-key",
+            ]);
         }
     }
 }
@@ -0,0 +1,325 @@
+//! Two body orbit propagation from Keplerian elements.
+//!
+//! Positions are in AU and velocities in AU per day, in the J2000 ecliptic frame
+//! (x towards the vernal equinox, z towards the ecliptic north pole). Times are julian dates.
+
+use std::fmt;
+use std::ops::{Add, Mul, Sub};
+
+use chrono::{Datelike, NaiveDate, NaiveDateTime, Timelike};
+
+use crate::nasa::models::OrbitalData;
+
+/// Kilometers per astronomical unit
+pub const AU_KM: f64 = 149_597_870.7;
+/// Julian date of 2000-01-01 12:00, the epoch of the J2000 frame
+pub const J2000: f64 = 2_451_545.0;
+/// Julian date of 0000-12-31 00:00 (proleptic gregorian), the day before chrono's `num_days_from_ce` starts counting
+const COMMON_ERA_JULIAN_DATE: f64 = 1_721_424.5;
+/// Gaussian gravitational constant, the sun's mean motion for a 1 AU orbit in radians per day
+const GAUSSIAN_GRAVITATIONAL_CONSTANT: f64 = 0.01720209895;
+
+const KEPLER_TOLERANCE: f64 = 1e-12;
+const KEPLER_MAX_ITERATIONS: usize = 50;
+
+/// Errors from propagating an orbit
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// Only elliptic orbits can be propagated, parabolic and hyperbolic orbits (eccentricity >= 1) can't
+    NotElliptic { eccentricity: f64 },
+    /// The semi major axis has to be positive
+    InvalidSemiMajorAxis { semi_major_axis: f64 },
+    /// Kepler's equation did not converge for the mean anomaly (in radians)
+    NoConvergence { mean_anomaly: f64 },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::NotElliptic { eccentricity } => write!(f, "orbit with eccentricity {} is not elliptic", eccentricity),
+            Error::InvalidSemiMajorAxis { semi_major_axis } => write!(f, "invalid semi major axis {}", semi_major_axis),
+            Error::NoConvergence { mean_anomaly } => write!(f, "kepler's equation did not converge for mean anomaly {}", mean_anomaly),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Vector3 {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+impl Vector3 {
+    pub fn new(x: f64, y: f64, z: f64) -> Self {
+        Self { x, y, z }
+    }
+
+    pub fn length(&self) -> f64 {
+        (self.x * self.x + self.y * self.y + self.z * self.z).sqrt()
+    }
+}
+
+impl Add for Vector3 {
+    type Output = Vector3;
+    fn add(self, other: Vector3) -> Vector3 {
+        Vector3::new(self.x + other.x, self.y + other.y, self.z + other.z)
+    }
+}
+
+impl Sub for Vector3 {
+    type Output = Vector3;
+    fn sub(self, other: Vector3) -> Vector3 {
+        Vector3::new(self.x - other.x, self.y - other.y, self.z - other.z)
+    }
+}
+
+impl Mul<f64> for Vector3 {
+    type Output = Vector3;
+    fn mul(self, scale: f64) -> Vector3 {
+        Vector3::new(self.x * scale, self.y * scale, self.z * scale)
+    }
+}
+
+/// Position (AU) and velocity (AU per day)
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct StateVector {
+    pub position: Vector3,
+    pub velocity: Vector3,
+}
+
+impl Sub for StateVector {
+    type Output = StateVector;
+    fn sub(self, other: StateVector) -> StateVector {
+        StateVector {
+            position: self.position - other.position,
+            velocity: self.velocity - other.velocity,
+        }
+    }
+}
+
+/// Heliocentric Keplerian elements, angles are in degrees
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OrbitalElements {
+    /// julian date the elements are valid for
+    pub epoch: f64,
+    pub eccentricity: f64,
+    /// in AU
+    pub semi_major_axis: f64,
+    pub inclination: f64,
+    pub ascending_node_longitude: f64,
+    pub perihelion_argument: f64,
+    /// at `epoch`
+    pub mean_anomaly: f64,
+    /// in degrees per day, derived from the semi major axis when not set
+    pub mean_motion: Option<f64>,
+}
+
+impl From<&OrbitalData> for OrbitalElements {
+    fn from(data: &OrbitalData) -> Self {
+        Self {
+            epoch: data.epoch_osculation,
+            eccentricity: data.eccentricity,
+            semi_major_axis: data.semi_major_axis,
+            inclination: data.inclination,
+            ascending_node_longitude: data.ascending_node_longitude,
+            perihelion_argument: data.perihelion_argument,
+            mean_anomaly: data.mean_anomaly,
+            mean_motion: data.mean_motion,
+        }
+    }
+}
+
+impl OrbitalElements {
+    /// Mean elements of the Earth-Moon barycenter at J2000 (Standish, "Keplerian Elements for Approximate Positions
+    /// of the Major Planets"), good to about 0.0001 AU for a few decades around 2000
+    pub fn earth() -> Self {
+        const MEAN_LONGITUDE: f64 = 100.46457166;
+        const PERIHELION_LONGITUDE: f64 = 102.93768193;
+        Self {
+            epoch: J2000,
+            eccentricity: 0.01671123,
+            semi_major_axis: 1.00000261,
+            inclination: -0.00001531,
+            ascending_node_longitude: 0.0,
+            perihelion_argument: PERIHELION_LONGITUDE,
+            mean_anomaly: MEAN_LONGITUDE - PERIHELION_LONGITUDE,
+            mean_motion: None,
+        }
+    }
+
+    /// Mean motion in degrees per day
+    pub fn mean_motion(&self) -> f64 {
+        self.mean_motion.unwrap_or_else(|| {
+            (GAUSSIAN_GRAVITATIONAL_CONSTANT / self.semi_major_axis.powf(1.5)).to_degrees()
+        })
+    }
+
+    /// Heliocentric ecliptic position and velocity at the julian date
+    pub fn heliocentric(&self, julian_date: f64) -> Result<StateVector, Error> {
+        let e = self.eccentricity;
+        let a = self.semi_major_axis;
+        if !(0.0..1.0).contains(&e) {
+            return Err(Error::NotElliptic { eccentricity: e });
+        }
+        if a <= 0.0 || !a.is_finite() {
+            return Err(Error::InvalidSemiMajorAxis { semi_major_axis: a });
+        }
+
+        let mean_motion = self.mean_motion().to_radians();
+        let mean_anomaly = self.mean_anomaly.to_radians() + mean_motion * (julian_date - self.epoch);
+        let eccentric_anomaly = solve_kepler(mean_anomaly, e)?;
+        let (sin_e, cos_e) = eccentric_anomaly.sin_cos();
+
+        // position and velocity in the orbital plane, x towards perihelion
+        let b = a * (1.0 - e * e).sqrt();
+        let plane_position = (a * (cos_e - e), b * sin_e);
+        let rate = mean_motion / (1.0 - e * cos_e);
+        let plane_velocity = (-a * sin_e * rate, b * cos_e * rate);
+
+        let rotation = self.rotation();
+        Ok(StateVector {
+            position: rotate(&rotation, plane_position),
+            velocity: rotate(&rotation, plane_velocity),
+        })
+    }
+
+    /// Position and velocity relative to the Earth at the julian date, in the same ecliptic frame
+    pub fn geocentric(&self, julian_date: f64) -> Result<StateVector, Error> {
+        Ok(self.heliocentric(julian_date)? - OrbitalElements::earth().heliocentric(julian_date)?)
+    }
+
+    /// Columns of the rotation from the orbital plane into the ecliptic frame, for the x and y axis of the plane
+    fn rotation(&self) -> [Vector3; 2] {
+        let (sin_node, cos_node) = self.ascending_node_longitude.to_radians().sin_cos();
+        let (sin_perihelion, cos_perihelion) = self.perihelion_argument.to_radians().sin_cos();
+        let (sin_inclination, cos_inclination) = self.inclination.to_radians().sin_cos();
+        [
+            Vector3::new(
+                cos_node * cos_perihelion - sin_node * sin_perihelion * cos_inclination,
+                sin_node * cos_perihelion + cos_node * sin_perihelion * cos_inclination,
+                sin_perihelion * sin_inclination,
+            ),
+            Vector3::new(
+                -cos_node * sin_perihelion - sin_node * cos_perihelion * cos_inclination,
+                -sin_node * sin_perihelion + cos_node * cos_perihelion * cos_inclination,
+                cos_perihelion * sin_inclination,
+            ),
+        ]
+    }
+}
+
+fn rotate(rotation: &[Vector3; 2], (x, y): (f64, f64)) -> Vector3 {
+    rotation[0] * x + rotation[1] * y
+}
+
+/// Solve Kepler's equation `E - e sin(E) = M` for the eccentric anomaly `E` (radians) with newton's method
+pub fn solve_kepler(mean_anomaly: f64, eccentricity: f64) -> Result<f64, Error> {
+    let mean_anomaly = mean_anomaly.rem_euclid(std::f64::consts::TAU);
+    // starting at pi converges for every eccentricity, closer starting points are only better for small ones
+    let mut eccentric_anomaly = if eccentricity < 0.8 {
+        mean_anomaly + eccentricity * mean_anomaly.sin()
+    } else {
+        std::f64::consts::PI
+    };
+    for _ in 0..KEPLER_MAX_ITERATIONS {
+        let delta = (eccentric_anomaly - eccentricity * eccentric_anomaly.sin() - mean_anomaly)
+            / (1.0 - eccentricity * eccentric_anomaly.cos());
+        eccentric_anomaly -= delta;
+        if delta.abs() < KEPLER_TOLERANCE {
+            return Ok(eccentric_anomaly);
+        }
+    }
+    Err(Error::NoConvergence { mean_anomaly })
+}
+
+/// Julian date of a UTC date time, the difference to TDB (about a minute) is ignored
+pub fn julian_date(date_time: NaiveDateTime) -> f64 {
+    let seconds = date_time.num_seconds_from_midnight() as f64 + date_time.nanosecond() as f64 / 1e9;
+    COMMON_ERA_JULIAN_DATE + date_time.num_days_from_ce() as f64 + seconds / 86_400.0
+}
+
+/// UTC date time of a julian date (to the millisecond), the inverse of [`julian_date`]. `None` if it's out of chrono's range.
+pub fn date_time(julian_date: f64) -> Option<NaiveDateTime> {
+    let days = julian_date - COMMON_ERA_JULIAN_DATE;
+    let whole_days = days.floor();
+    let milliseconds = ((days - whole_days) * 86_400_000.0).round() as i64;
+    let midnight = NaiveDate::from_num_days_from_ce_opt(whole_days as i32)?.and_hms_opt(0, 0, 0)?;
+    Some(midnight + chrono::Duration::milliseconds(milliseconds))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: Vector3, expected: Vector3, tolerance: f64) {
+        assert!((actual - expected).length() < tolerance, "{:?} is not within {} of {:?}", actual, tolerance, expected);
+    }
+
+    #[test]
+    fn earth_at_j2000() {
+        // JPL Horizons, Earth-Moon barycenter at 2000-01-01 12:00 TDB
+        let state = OrbitalElements::earth().heliocentric(J2000).unwrap();
+        assert_close(state.position, Vector3::new(-0.1771, 0.9672, 0.0), 1e-3);
+        assert_close(state.velocity, Vector3::new(-0.01720, -0.00316, 0.0), 1e-4);
+    }
+
+    #[test]
+    fn mars_at_j2000() {
+        // Standish mean elements, compared against JPL Horizons for 2000-01-01 12:00 TDB
+        let mars = OrbitalElements {
+            epoch: J2000,
+            eccentricity: 0.09339410,
+            semi_major_axis: 1.52371034,
+            inclination: 1.84969142,
+            ascending_node_longitude: 49.55953891,
+            perihelion_argument: -23.94362959 - 49.55953891,
+            mean_anomaly: -4.55343205 + 23.94362959,
+            mean_motion: None,
+        };
+        let state = mars.heliocentric(J2000).unwrap();
+        assert_close(state.position, Vector3::new(1.3907, -0.0134, -0.0345), 2e-3);
+    }
+
+    #[test]
+    fn orbit_repeats_after_one_period() {
+        let elements = OrbitalElements {
+            epoch: J2000,
+            eccentricity: 0.6,
+            semi_major_axis: 2.2,
+            inclination: 12.,
+            ascending_node_longitude: 80.,
+            perihelion_argument: 150.,
+            mean_anomaly: 33.,
+            mean_motion: None,
+        };
+        let period = 360. / elements.mean_motion();
+        let start = elements.heliocentric(J2000).unwrap();
+        let end = elements.heliocentric(J2000 + period).unwrap();
+        assert_close(end.position, start.position, 1e-9);
+        assert_close(end.velocity, start.velocity, 1e-9);
+    }
+
+    #[test]
+    fn solve_kepler_for_high_eccentricity() {
+        for eccentricity in [0.0, 0.5, 0.9, 0.99] {
+            for mean_anomaly in [0.001, 1.0, 3.1, 5.9] {
+                let eccentric_anomaly = solve_kepler(mean_anomaly, eccentricity).unwrap();
+                assert!((eccentric_anomaly - eccentricity * eccentric_anomaly.sin() - mean_anomaly).abs() < 1e-10);
+            }
+        }
+    }
+
+    #[test]
+    fn julian_date_of_j2000() {
+        let date_time = NaiveDateTime::parse_from_str("2000-01-01 12:00", "%F %H:%M").unwrap();
+        assert_eq!(julian_date(date_time), J2000);
+        let date_time = NaiveDateTime::parse_from_str("1970-01-01 00:00", "%F %H:%M").unwrap();
+        assert_eq!(julian_date(date_time), 2_440_587.5);
+        assert_eq!(super::date_time(julian_date(date_time)), Some(date_time));
+        let date_time = NaiveDateTime::parse_from_str("2022-01-08 14:34", "%F %H:%M").unwrap();
+        assert_eq!(super::date_time(julian_date(date_time)), Some(date_time));
+    }
+}
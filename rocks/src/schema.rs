@@ -4,5 +4,60 @@ table! {
         start_date -> Text,
         end_date -> Text,
         response -> Text,
+        fetched_at -> Nullable<Timestamp>,
     }
 }
+
+table! {
+    close_approach (id) {
+        id -> Integer,
+        near_earth_object_id -> Text,
+        close_approach_date -> Date,
+        close_approach_date_full -> Timestamp,
+        epoch_date_close_approach -> BigInt,
+        relative_velocity_kilometers_per_second -> Double,
+        relative_velocity_kilometers_per_hour -> Double,
+        relative_velocity_miles_per_hour -> Double,
+        miss_distance_astronomical -> Double,
+        miss_distance_lunar -> Double,
+        miss_distance_kilometers -> Double,
+        miss_distance_miles -> Double,
+        orbiting_body -> Text,
+    }
+}
+
+table! {
+    import_failure (id) {
+        id -> Integer,
+        source -> Text,
+        start_date -> Nullable<Text>,
+        end_date -> Nullable<Text>,
+        reason -> Text,
+        status -> Nullable<Integer>,
+        body -> Nullable<Text>,
+        failed_at -> Timestamp,
+    }
+}
+
+table! {
+    near_earth_object (id) {
+        id -> Text,
+        neo_reference_id -> Text,
+        name -> Text,
+        nasa_jpl_url -> Text,
+        absolute_magnitude_h -> Double,
+        estimated_diameter_min_km -> Double,
+        estimated_diameter_max_km -> Double,
+        is_potentially_hazardous_asteroid -> Bool,
+        is_sentry_object -> Bool,
+    }
+}
+
+joinable!(close_approach -> near_earth_object (near_earth_object_id));
+
+allow_tables_to_appear_in_same_query!(
+    api_response,
+    close_approach,
+    import_failure,
+    near_earth_object,
+);
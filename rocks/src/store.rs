@@ -0,0 +1,449 @@
+use std::collections::HashMap;
+
+use chrono::{NaiveDate, NaiveDateTime, Utc};
+use diesel::prelude::*;
+use diesel::SqliteConnection;
+
+use crate::db_util::{self, Error};
+use crate::nasa::client::is_window_complete;
+use crate::models::{self, ApiResponse, ImportFailure, NewApiResponse, NewImportFailure};
+use crate::nasa::models::{
+    CloseApproachEvent, EstimatedDiameter, EstimatedDiameters, Links, MissDistance, NearEarthObject,
+    NearEarthObjectResponse, RelativeVelocity,
+};
+use crate::schema::{api_response, close_approach, import_failure, near_earth_object};
+
+// NASA's conversions from kilometers, only kilometers are stored
+const METERS_PER_KILOMETER: f64 = 1000.;
+const MILES_PER_KILOMETER: f64 = 0.621371192;
+const FEET_PER_KILOMETER: f64 = 3280.8398950131;
+
+/// Date window of a stored feed response
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredWindow {
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+    pub fetched_at: Option<NaiveDateTime>,
+}
+
+impl StoredWindow {
+    /// Whether the window was fetched after it was over, otherwise NASA may have more close approaches for it now.
+    /// Uses the same rule as the response cache, see [`is_window_complete`].
+    pub fn is_complete(&self) -> bool {
+        is_window_complete(self.end_date, self.fetched_at)
+    }
+}
+
+/// Filters for [`NeoStore::search`], filters that aren't set match everything
+#[derive(Debug, Clone, Default)]
+pub struct SearchFilter {
+    /// first close approach date to include
+    pub start_date: Option<NaiveDate>,
+    /// last close approach date to include
+    pub end_date: Option<NaiveDate>,
+    /// objects whose largest estimated diameter is at least this big
+    pub min_diameter_km: Option<f64>,
+    pub max_miss_distance_km: Option<f64>,
+    pub max_miss_distance_lunar: Option<f64>,
+    pub is_potentially_hazardous_asteroid: Option<bool>,
+    pub is_sentry_object: Option<bool>,
+    pub orbiting_body: Option<String>,
+}
+
+/// Queries over the stored asteroid data, results are returned as [`crate::nasa::models`] types.
+///
+/// Objects are rebuilt from the `near_earth_object` and `close_approach` tables, so fields that
+/// aren't stored there (links, orbital data, unknown fields) are left empty.
+pub struct NeoStore {
+    connection: SqliteConnection,
+}
+
+impl NeoStore {
+    pub fn new(connection: SqliteConnection) -> Self {
+        Self { connection }
+    }
+
+    pub fn connection(&self) -> &SqliteConnection {
+        &self.connection
+    }
+
+    /// Objects with a close approach matching the filter, ordered by their first matching approach.
+    /// Only the matching close approaches are included, so objects without any approaches never match.
+    pub fn search(&self, filter: &SearchFilter) -> Result<Vec<NearEarthObject>, Error> {
+        let mut query = close_approach::table
+            .inner_join(near_earth_object::table)
+            .select((close_approach::all_columns, near_earth_object::all_columns))
+            .into_boxed();
+        if let Some(start_date) = filter.start_date {
+            query = query.filter(close_approach::close_approach_date.ge(start_date));
+        }
+        if let Some(end_date) = filter.end_date {
+            query = query.filter(close_approach::close_approach_date.le(end_date));
+        }
+        if let Some(min_diameter_km) = filter.min_diameter_km {
+            query = query.filter(near_earth_object::estimated_diameter_max_km.ge(min_diameter_km));
+        }
+        if let Some(max_miss_distance_km) = filter.max_miss_distance_km {
+            query = query.filter(close_approach::miss_distance_kilometers.le(max_miss_distance_km));
+        }
+        if let Some(max_miss_distance_lunar) = filter.max_miss_distance_lunar {
+            query = query.filter(close_approach::miss_distance_lunar.le(max_miss_distance_lunar));
+        }
+        if let Some(hazardous) = filter.is_potentially_hazardous_asteroid {
+            query = query.filter(near_earth_object::is_potentially_hazardous_asteroid.eq(hazardous));
+        }
+        if let Some(sentry) = filter.is_sentry_object {
+            query = query.filter(near_earth_object::is_sentry_object.eq(sentry));
+        }
+        if let Some(orbiting_body) = &filter.orbiting_body {
+            query = query.filter(close_approach::orbiting_body.eq(orbiting_body));
+        }
+        let approaches = query
+            .order(close_approach::epoch_date_close_approach.asc())
+            .load::<(models::CloseApproach, models::NearEarthObject)>(&self.connection)?;
+        Ok(group_by_object(approaches))
+    }
+
+    /// Objects with a close approach between the dates (inclusive), ordered by their first approach.
+    /// Only the close approaches inside the range are included.
+    pub fn objects_between(&self, start_date: NaiveDate, end_date: NaiveDate) -> Result<Vec<NearEarthObject>, Error> {
+        let approaches = close_approach::table
+            .inner_join(near_earth_object::table)
+            .filter(close_approach::close_approach_date.between(start_date, end_date))
+            .order(close_approach::epoch_date_close_approach.asc())
+            .load::<(models::CloseApproach, models::NearEarthObject)>(&self.connection)?;
+        Ok(group_by_object(approaches))
+    }
+
+    /// The `limit` closest approaches by miss distance, closest first. Each object only includes the
+    /// one approach, so an object with several close approaches can show up more than once.
+    pub fn closest_approaches(&self, limit: i64) -> Result<Vec<NearEarthObject>, Error> {
+        let approaches = close_approach::table
+            .inner_join(near_earth_object::table)
+            .order(close_approach::miss_distance_kilometers.asc())
+            .limit(limit)
+            .load::<(models::CloseApproach, models::NearEarthObject)>(&self.connection)?;
+        Ok(approaches.into_iter()
+            .map(|(approach, object)| to_near_earth_object(&object, vec![approach]))
+            .collect())
+    }
+
+    /// Every potentially hazardous object with all of its close approaches
+    pub fn hazardous_only(&self) -> Result<Vec<NearEarthObject>, Error> {
+        let approaches = close_approach::table
+            .inner_join(near_earth_object::table)
+            .filter(near_earth_object::is_potentially_hazardous_asteroid.eq(true))
+            .order(close_approach::epoch_date_close_approach.asc())
+            .load::<(models::CloseApproach, models::NearEarthObject)>(&self.connection)?;
+        let mut objects = group_by_object(approaches);
+        // objects without any stored approaches still count
+        let hazardous = near_earth_object::table
+            .filter(near_earth_object::is_potentially_hazardous_asteroid.eq(true))
+            .load::<models::NearEarthObject>(&self.connection)?;
+        for object in hazardous {
+            if !objects.iter().any(|found| found.id == object.id) {
+                objects.push(to_near_earth_object(&object, Vec::new()));
+            }
+        }
+        Ok(objects)
+    }
+
+    /// A single object with all of its close approaches
+    pub fn by_id(&self, id: &str) -> Result<Option<NearEarthObject>, Error> {
+        let object = near_earth_object::table
+            .find(id)
+            .first::<models::NearEarthObject>(&self.connection)
+            .optional()?;
+        match object {
+            Some(object) => {
+                let approaches = close_approach::table
+                    .filter(close_approach::near_earth_object_id.eq(id))
+                    .order(close_approach::epoch_date_close_approach.asc())
+                    .load::<models::CloseApproach>(&self.connection)?;
+                Ok(Some(to_near_earth_object(&object, approaches)))
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// Every raw response that has been stored
+    pub fn api_responses(&self) -> Result<Vec<ApiResponse>, Error> {
+        Ok(api_response::table.load::<ApiResponse>(&self.connection)?)
+    }
+
+    /// Date windows of every stored raw response, rows with dates that can't be parsed are skipped
+    pub fn stored_windows(&self) -> Result<Vec<StoredWindow>, Error> {
+        let windows = api_response::table
+            .select((api_response::start_date, api_response::end_date, api_response::fetched_at))
+            .load::<(String, String, Option<NaiveDateTime>)>(&self.connection)?;
+        Ok(windows.into_iter()
+            .filter_map(|(start_date, end_date, fetched_at)| {
+                match (NaiveDate::parse_from_str(&start_date, "%F"), NaiveDate::parse_from_str(&end_date, "%F")) {
+                    (Ok(start_date), Ok(end_date)) => Some(StoredWindow { start_date, end_date, fetched_at }),
+                    _ => {
+                        warn!("Skipping stored response with invalid window start_date={} end_date={}", start_date, end_date);
+                        None
+                    }
+                }
+            })
+            .collect())
+    }
+
+    /// The stored raw response for exactly this feed window
+    pub fn api_response(&self, start_date: &str, end_date: &str) -> Result<Option<ApiResponse>, Error> {
+        Ok(api_response::table
+            .filter(api_response::start_date.eq(start_date))
+            .filter(api_response::end_date.eq(end_date))
+            .first::<ApiResponse>(&self.connection)
+            .optional()?)
+    }
+
+    /// Store the body of a feed response exactly as NASA sent it and project it into the queryable tables.
+    /// Storing the same window again replaces the previous response. Returns the number of objects projected.
+    pub fn upsert_response(&self, start_date: &str, end_date: &str, response: &str) -> Result<usize, Error> {
+        self.upsert_response_fetched_at(start_date, end_date, response, Some(Utc::now().naive_utc()))
+    }
+
+    /// Same as [`NeoStore::upsert_response`] for a response that was fetched earlier (ex. saved to a file),
+    /// `None` if it's not known when it was fetched
+    pub fn upsert_response_fetched_at(&self, start_date: &str, end_date: &str, response: &str, fetched_at: Option<NaiveDateTime>) -> Result<usize, Error> {
+        // decoding first so a body that isn't a feed response never gets stored
+        let decoded = serde_json::from_str::<NearEarthObjectResponse>(response)?;
+        self.connection.transaction::<_, Error, _>(|| {
+            let new_api_response = NewApiResponse {
+                start_date,
+                end_date,
+                response,
+                fetched_at,
+            };
+            // the table replaces rows on a conflicting start_date and end_date
+            diesel::insert_into(api_response::table).values(&new_api_response).execute(&self.connection)?;
+            db_util::project_response(&self.connection, &decoded)
+        })
+    }
+
+    /// Keep a response that was rejected so it can be looked at later, nothing is projected
+    pub fn record_failure(&self, failure: &NewImportFailure) -> Result<(), Error> {
+        diesel::insert_into(import_failure::table).values(failure).execute(&self.connection)?;
+        Ok(())
+    }
+
+    /// Every recorded failure, most recent first
+    pub fn import_failures(&self) -> Result<Vec<ImportFailure>, Error> {
+        Ok(import_failure::table
+            .order(import_failure::failed_at.desc())
+            .load::<ImportFailure>(&self.connection)?)
+    }
+}
+
+/// Group joined close approaches (already in the order we want) under their objects
+fn group_by_object(approaches: Vec<(models::CloseApproach, models::NearEarthObject)>) -> Vec<NearEarthObject> {
+    let mut order: Vec<models::NearEarthObject> = Vec::new();
+    let mut grouped: HashMap<String, Vec<models::CloseApproach>> = HashMap::new();
+    for (approach, object) in approaches {
+        if !grouped.contains_key(&object.id) {
+            order.push(object);
+        }
+        grouped.entry(approach.near_earth_object_id.clone()).or_default().push(approach);
+    }
+    order.into_iter()
+        .map(|object| {
+            let approaches = grouped.remove(&object.id).unwrap_or_default();
+            to_near_earth_object(&object, approaches)
+        })
+        .collect()
+}
+
+fn to_near_earth_object(object: &models::NearEarthObject, approaches: Vec<models::CloseApproach>) -> NearEarthObject {
+    let diameter = |per_kilometer: f64| EstimatedDiameter {
+        estimated_diameter_min: object.estimated_diameter_min_km * per_kilometer,
+        estimated_diameter_max: object.estimated_diameter_max_km * per_kilometer,
+    };
+    NearEarthObject {
+        links: Links { next: None, prev: None, self_link: None },
+        id: object.id.clone(),
+        neo_reference_id: object.neo_reference_id.clone(),
+        name: object.name.clone(),
+        nasa_jpl_url: object.nasa_jpl_url.clone(),
+        absolute_magnitude_h: object.absolute_magnitude_h,
+        estimated_diameter: EstimatedDiameters {
+            kilometers: diameter(1.),
+            meters: diameter(METERS_PER_KILOMETER),
+            miles: diameter(MILES_PER_KILOMETER),
+            feet: diameter(FEET_PER_KILOMETER),
+        },
+        is_potentially_hazardous_asteroid: object.is_potentially_hazardous_asteroid,
+        close_approach_data: approaches.into_iter().map(to_close_approach_event).collect(),
+        orbital_data: None,
+        is_sentry_object: object.is_sentry_object,
+        extra: HashMap::new(),
+    }
+}
+
+fn to_close_approach_event(approach: models::CloseApproach) -> CloseApproachEvent {
+    CloseApproachEvent {
+        close_approach_date: approach.close_approach_date,
+        close_approach_date_full: approach.close_approach_date_full,
+        epoch_date_close_approach: approach.epoch_date_close_approach,
+        relative_velocity: RelativeVelocity {
+            kilometers_per_second: approach.relative_velocity_kilometers_per_second,
+            kilometers_per_hour: approach.relative_velocity_kilometers_per_hour,
+            miles_per_hour: approach.relative_velocity_miles_per_hour,
+        },
+        miss_distance: MissDistance {
+            astronomical: approach.miss_distance_astronomical,
+            lunar: approach.miss_distance_lunar,
+            kilometers: approach.miss_distance_kilometers,
+            miles: approach.miss_distance_miles,
+        },
+        orbiting_body: approach.orbiting_body,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::nasa::models::tests::{date, object_json_with};
+
+    fn store() -> NeoStore {
+        let options = db_util::ConnectionOptions { foreign_keys: true, ..Default::default() };
+        NeoStore::new(db_util::try_establish_connection(":memory:", &options).unwrap())
+    }
+
+    fn response(objects: &[String]) -> String {
+        format!(r#"{{
+            "links": {{}},
+            "element_count": {},
+            "near_earth_objects": {{"2022-01-08": [{}]}}
+        }}"#, objects.len(), objects.join(","))
+    }
+
+    fn fixture() -> String {
+        response(&[
+            object_json_with("1", false, &[("2022-01-08", 1641643200000, "5000000")]),
+            object_json_with("2", true, &[("2022-01-09", 1641729600000, "1000000"), ("2022-01-10", 1641816000000, "9000000")]),
+            object_json_with("3", true, &[]),
+        ])
+    }
+
+    #[test]
+    fn upsert_response_is_idempotent() {
+        let store = store();
+        assert_eq!(store.upsert_response("2022-01-08", "2022-01-10", &fixture()).unwrap(), 3);
+        assert_eq!(store.upsert_response("2022-01-08", "2022-01-10", &fixture()).unwrap(), 3);
+
+        assert_eq!(store.api_responses().unwrap().len(), 1);
+        let num_approaches: i64 = close_approach::table.count().get_result(store.connection()).unwrap();
+        assert_eq!(num_approaches, 3);
+    }
+
+    #[test]
+    fn upsert_response_keeps_the_raw_body() {
+        let store = store();
+        // more digits than an f64 keeps, decoding and encoding again would change them
+        let json = response(&[object_json_with("1", false, &[("2022-01-08", 1641643200000, "46677510.425426824")])]);
+        store.upsert_response("2022-01-08", "2022-01-08", &json).unwrap();
+
+        let stored = store.api_response("2022-01-08", "2022-01-08").unwrap().unwrap();
+        assert_eq!(stored.response, json);
+        assert!(store.api_response("2022-01-08", "2022-01-09").unwrap().is_none());
+
+        assert!(matches!(store.upsert_response("2022-01-09", "2022-01-09", "{\"links\": {}}"), Err(Error::Json(_))));
+        assert!(store.api_response("2022-01-09", "2022-01-09").unwrap().is_none());
+    }
+
+    #[test]
+    fn by_id_returns_all_approaches() {
+        let store = store();
+        store.upsert_response("2022-01-08", "2022-01-10", &fixture()).unwrap();
+
+        let object = store.by_id("2").unwrap().unwrap();
+        assert_eq!(object.name, "(2)");
+        assert!(object.is_potentially_hazardous_asteroid);
+        assert_eq!(object.estimated_diameter.meters.estimated_diameter_max, 500.);
+        assert_eq!(object.close_approach_data.len(), 2);
+        assert_eq!(object.close_approach_data[0].miss_distance.kilometers, 1000000.);
+        assert!(store.by_id("missing").unwrap().is_none());
+    }
+
+    #[test]
+    fn objects_between_filters_approaches_by_date() {
+        let store = store();
+        store.upsert_response("2022-01-08", "2022-01-10", &fixture()).unwrap();
+
+        let objects = store.objects_between(date("2022-01-09"), date("2022-01-09")).unwrap();
+        assert_eq!(objects.len(), 1);
+        assert_eq!(objects[0].id, "2");
+        assert_eq!(objects[0].close_approach_data.len(), 1);
+
+        let objects = store.objects_between(date("2022-01-08"), date("2022-01-10")).unwrap();
+        let ids: Vec<&str> = objects.iter().map(|object| object.id.as_str()).collect();
+        assert_eq!(ids, vec!["1", "2"]);
+    }
+
+    #[test]
+    fn closest_approaches_are_ordered_by_miss_distance() {
+        let store = store();
+        store.upsert_response("2022-01-08", "2022-01-10", &fixture()).unwrap();
+
+        let objects = store.closest_approaches(2).unwrap();
+        let distances: Vec<(&str, f64)> = objects.iter()
+            .map(|object| (object.id.as_str(), object.close_approach_data[0].miss_distance.kilometers))
+            .collect();
+        assert_eq!(distances, vec![("2", 1000000.), ("1", 5000000.)]);
+    }
+
+    #[test]
+    fn hazardous_only_includes_objects_without_approaches() {
+        let store = store();
+        store.upsert_response("2022-01-08", "2022-01-10", &fixture()).unwrap();
+
+        let mut ids: Vec<String> = store.hazardous_only().unwrap().into_iter().map(|object| object.id).collect();
+        ids.sort();
+        assert_eq!(ids, vec!["2", "3"]);
+    }
+
+    #[test]
+    fn search_combines_filters() {
+        let store = store();
+        store.upsert_response("2022-01-08", "2022-01-10", &fixture()).unwrap();
+
+        let filter = SearchFilter {
+            start_date: Some(date("2022-01-09")),
+            max_miss_distance_km: Some(2000000.),
+            is_potentially_hazardous_asteroid: Some(true),
+            orbiting_body: Some(String::from("Earth")),
+            ..Default::default()
+        };
+        let objects = store.search(&filter).unwrap();
+        assert_eq!(objects.len(), 1);
+        assert_eq!(objects[0].id, "2");
+        assert_eq!(objects[0].close_approach_data.len(), 1);
+
+        let filter = SearchFilter { min_diameter_km: Some(1.), ..Default::default() };
+        assert!(store.search(&filter).unwrap().is_empty());
+        assert_eq!(store.search(&SearchFilter::default()).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn record_failure_is_not_stored_as_a_response() {
+        let store = store();
+        let failure = NewImportFailure {
+            source: "/neo/rest/v1/feed",
+            start_date: Some("2022-01-08"),
+            end_date: Some("2022-01-15"),
+            reason: "unexpected status 403 Forbidden",
+            status: Some(403),
+            body: Some(r#"{"error": {"code": "API_KEY_INVALID"}}"#),
+            failed_at: Utc::now().naive_utc(),
+        };
+        store.record_failure(&failure).unwrap();
+
+        assert!(store.api_responses().unwrap().is_empty());
+        let failures = store.import_failures().unwrap();
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].status, Some(403));
+        assert_eq!(failures[0].start_date.as_deref(), Some("2022-01-08"));
+    }
+}